@@ -0,0 +1,333 @@
+//! Builds `data/japan.svg` from Natural Earth's public-domain admin-1
+//! boundaries ("states and provinces", 1:10m), in the format `map.rs` reads:
+//! one `<path>` per prefecture, its id the ISO 3166-2 code, drawn with
+//! absolute M/L/Z commands and integer coordinates.
+//!
+//! ```text
+//! cargo run --example japan_svg -- ne_10m_admin_1_states_provinces.geojson > data/japan.svg
+//! ```
+//!
+//! The input is the GeoJSON edition of the dataset; features are picked by
+//! their `iso_3166_2` property. Borders are simplified one shared stretch at
+//! a time, so neighbouring prefectures keep meeting without gaps. Polygons
+//! centred outside the main frame are left out (Ogasawara, Okinotorishima,
+//! Minamitorishima, the Daitō Islands), except that Kagoshima's and
+//! Okinawa's islands south of 30°N go into a smaller inset at the bottom
+//! right. The top left stays open sea for the export legend.
+
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::{env, fs, process};
+
+type Point = (f64, f64);
+
+struct Bounds {
+    west: f64,
+    east: f64,
+    south: f64,
+    north: f64,
+}
+
+impl Bounds {
+    fn contains(&self, (lon, lat): Point) -> bool {
+        (self.west..=self.east).contains(&lon) && (self.south..=self.north).contains(&lat)
+    }
+}
+
+const MAIN: Bounds = Bounds { west: 128.0, east: 146.0, south: 30.0, north: 46.0 };
+const INSET: Bounds = Bounds { west: 122.5, east: 130.5, south: 24.0, north: 30.0 };
+const INSET_PREFECTURES: [&str; 2] = ["JP-46", "JP-47"];
+
+const PIXELS_PER_DEGREE: f64 = 115.0;
+const INSET_SCALE: f64 = 0.6;
+// Equirectangular, with longitudes shortened to their length at this latitude
+const STANDARD_PARALLEL: f64 = 36.5;
+const MARGIN: f64 = 20.0;
+// Douglas-Peucker tolerance, and the smallest island or hole kept, in pixels
+const TOLERANCE: f64 = 1.2;
+const MIN_AREA: f64 = 12.0;
+
+struct Polygon {
+    feature: usize,
+    inset: bool,
+    rings: Vec<Vec<Point>>, // outer ring first, (lon, lat), not closed
+}
+
+fn main() {
+    let Some(input) = env::args().nth(1) else {
+        eprintln!("usage: japan_svg <ne_10m_admin_1_states_provinces.geojson>");
+        process::exit(2);
+    };
+    let geojson: Value = match fs::read_to_string(&input).map(|text| serde_json::from_str(&text)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => fail(&format!("{}: {}", input, e)),
+        Err(e) => fail(&format!("{}: {}", input, e)),
+    };
+
+    let (codes, polygons) = read_prefectures(&geojson);
+    if codes.len() != 47 {
+        eprintln!("warning: found {} prefectures, expected 47", codes.len());
+    }
+    print!("{}", render(&codes, &polygons));
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("japan_svg: {}", message);
+    process::exit(1);
+}
+
+// ISO codes by feature index, and every polygon that belongs on the map
+fn read_prefectures(geojson: &Value) -> (Vec<String>, Vec<Polygon>) {
+    let mut codes = Vec::new();
+    let mut polygons = Vec::new();
+    let features = geojson["features"].as_array().map_or(&[][..], Vec::as_slice);
+    for feature in features {
+        let Some(code) = feature["properties"]["iso_3166_2"].as_str() else { continue };
+        let is_prefecture = code.len() == 5
+            && code.starts_with("JP-")
+            && code[3..].bytes().all(|b| b.is_ascii_digit());
+        if !is_prefecture {
+            continue;
+        }
+
+        let geometry = &feature["geometry"];
+        let parts = match geometry["type"].as_str() {
+            Some("Polygon") => vec![&geometry["coordinates"]],
+            Some("MultiPolygon") => geometry["coordinates"].as_array().map_or(Vec::new(), |p| p.iter().collect()),
+            _ => Vec::new(),
+        };
+        let index = codes.len();
+        for part in parts {
+            let rings: Vec<Vec<Point>> = part
+                .as_array()
+                .map_or(&[][..], Vec::as_slice)
+                .iter()
+                .map(read_ring)
+                .filter(|ring| ring.len() >= 3)
+                .collect();
+            let Some(outer) = rings.first() else { continue };
+
+            let centre = bounding_centre(outer);
+            let inset = if MAIN.contains(centre) {
+                false
+            } else if INSET_PREFECTURES.contains(&code) && INSET.contains(centre) {
+                true
+            } else {
+                continue;
+            };
+            polygons.push(Polygon { feature: index, inset, rings });
+        }
+        codes.push(code.to_string());
+    }
+    (codes, polygons)
+}
+
+fn read_ring(ring: &Value) -> Vec<Point> {
+    let mut points: Vec<Point> = ring
+        .as_array()
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter_map(|p| Some((p[0].as_f64()?, p[1].as_f64()?)))
+        .collect();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    points
+}
+
+fn bounding_centre(ring: &[Point]) -> Point {
+    let (mut west, mut east) = (f64::MAX, f64::MIN);
+    let (mut south, mut north) = (f64::MAX, f64::MIN);
+    for &(lon, lat) in ring {
+        west = west.min(lon);
+        east = east.max(lon);
+        south = south.min(lat);
+        north = north.max(lat);
+    }
+    ((west + east) / 2.0, (south + north) / 2.0)
+}
+
+fn render(codes: &[String], polygons: &[Polygon]) -> String {
+    // Which prefectures use each vertex, to find where shared borders start
+    // and end
+    let mut users: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    for polygon in polygons {
+        for &point in polygon.rings.iter().flatten() {
+            let entry = users.entry(key(point)).or_default();
+            if !entry.contains(&polygon.feature) {
+                entry.push(polygon.feature);
+                entry.sort_unstable();
+            }
+        }
+    }
+
+    let project = |(lon, lat): Point, inset: bool| {
+        let scale = PIXELS_PER_DEGREE * if inset { INSET_SCALE } else { 1.0 };
+        (lon * STANDARD_PARALLEL.to_radians().cos() * scale, -lat * scale)
+    };
+    let extent = |inset: bool| {
+        let mut extent = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for polygon in polygons.iter().filter(|p| p.inset == inset) {
+            for &point in polygon.rings.iter().flatten() {
+                let (x, y) = project(point, inset);
+                extent = (extent.0.min(x), extent.1.min(y), extent.2.max(x), extent.3.max(y));
+            }
+        }
+        extent
+    };
+    let main = extent(false);
+    let width = (main.2 - main.0 + 2.0 * MARGIN).ceil();
+    let height = (main.3 - main.1 + 2.0 * MARGIN).ceil();
+    let inset = extent(true);
+    // Main map from the top left corner, inset against the bottom right
+    let offset = |inset_polygon: bool| {
+        if inset_polygon {
+            (width - MARGIN - inset.2, height - MARGIN - inset.3)
+        } else {
+            (MARGIN - main.0, MARGIN - main.1)
+        }
+    };
+    let inset_left = width - MARGIN - (inset.2 - inset.0);
+    let inset_top = height - MARGIN - (inset.3 - inset.1);
+    let covered = polygons.iter().filter(|p| !p.inset).flat_map(|p| p.rings.iter().flatten()).any(|&point| {
+        let (x, y) = project(point, false);
+        x - main.0 + MARGIN >= inset_left && y - main.1 + MARGIN >= inset_top
+    });
+    if covered {
+        eprintln!("warning: the inset overlaps the main map");
+    }
+
+    let mut paths: BTreeMap<&str, Vec<Vec<(i64, i64)>>> = BTreeMap::new();
+    for polygon in polygons {
+        let (dx, dy) = offset(polygon.inset);
+        let mut rings: Vec<Vec<(i64, i64)>> = Vec::new();
+        for (i, ring) in polygon.rings.iter().enumerate() {
+            let kept = simplify(ring, &users, |point| {
+                let (x, y) = project(point, polygon.inset);
+                (x + dx, y + dy)
+            });
+            let mut pixels: Vec<(i64, i64)> = kept.iter().map(|&(x, y)| (x.round() as i64, y.round() as i64)).collect();
+            pixels.dedup();
+            if pixels.len() > 1 && pixels.first() == pixels.last() {
+                pixels.pop();
+            }
+            let shared = ring.iter().any(|&point| users[&key(point)].len() > 1);
+            let tiny = area(&pixels) < MIN_AREA && !shared;
+            // A polygon whose outer ring is too small goes with its holes
+            if pixels.len() < 3 || tiny {
+                if i == 0 {
+                    break;
+                }
+                continue;
+            }
+            rings.push(pixels);
+        }
+        paths.entry(&codes[polygon.feature]).or_default().extend(rings);
+    }
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">\n\
+         \x20 <!-- Prefecture boundaries from Natural Earth (public domain), admin 1\n\
+         \x20      states and provinces at 1:10m, simplified by examples/japan_svg.rs.\n\
+         \x20      The islands south of 30°N are drawn in the inset at the bottom right.\n\
+         \x20      Each path id is the ISO 3166-2 code jterm keys progress by. -->\n\
+         \x20 <style>\n\
+         \x20   path {{ fill: #fcf9f3; stroke: #100f0d; stroke-width: 2; stroke-linejoin: round; fill-rule: evenodd; }}\n\
+         \x20 </style>\n",
+        width, height
+    );
+    for (code, rings) in &paths {
+        let mut d = String::new();
+        for ring in rings {
+            for (i, (x, y)) in ring.iter().enumerate() {
+                d.push_str(&format!("{}{} {}", if i == 0 { 'M' } else { 'L' }, x, y));
+            }
+            d.push('Z');
+        }
+        svg.push_str(&format!("  <path id=\"{}\" d=\"{}\"/>\n", code, d));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn key((lon, lat): Point) -> (u64, u64) {
+    (lon.to_bits(), lat.to_bits())
+}
+
+// Douglas-Peucker, run separately on each stretch between vertices where the
+// set of prefectures using them changes. Both sides of a border then see the
+// same stretch with the same ends, and walk it in the same direction.
+fn simplify(ring: &[Point], users: &HashMap<(u64, u64), Vec<usize>>, project: impl Fn(Point) -> Point) -> Vec<Point> {
+    let n = ring.len();
+    let users_at = |i: usize| &users[&key(ring[i % n])];
+    let mut fixed: Vec<usize> = (0..n)
+        .filter(|&i| users_at(i) != users_at(i + n - 1) || users_at(i) != users_at(i + 1))
+        .collect();
+    // A ring nobody else touches: anchor it at two far-apart vertices
+    if fixed.len() < 2 {
+        let first = fixed.first().copied().unwrap_or(0);
+        let distance = |i: &usize| {
+            let (a, b) = (ring[first], ring[*i]);
+            (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)
+        };
+        let far = (0..n).max_by(|a, b| distance(a).total_cmp(&distance(b))).unwrap_or(0);
+        fixed = vec![first.min(far), first.max(far)];
+    }
+
+    let mut kept = Vec::new();
+    for (j, &start) in fixed.iter().enumerate() {
+        let end = fixed.get(j + 1).copied().unwrap_or(fixed[0] + n);
+        let mut stretch: Vec<Point> = (start..=end).map(|i| ring[i % n]).collect();
+        let reversed = key(stretch[0]) > key(stretch[stretch.len() - 1]);
+        if reversed {
+            stretch.reverse();
+        }
+        let projected: Vec<Point> = stretch.into_iter().map(&project).collect();
+        let mut keep = vec![false; projected.len()];
+        keep[0] = true;
+        douglas_peucker(&projected, 0, projected.len() - 1, &mut keep);
+        let mut simplified: Vec<Point> = projected.iter().zip(&keep).filter(|(_, k)| **k).map(|(p, _)| *p).collect();
+        if reversed {
+            simplified.reverse();
+        }
+        // The end is the next stretch's start
+        simplified.pop();
+        kept.extend(simplified);
+    }
+    kept
+}
+
+fn douglas_peucker(points: &[Point], first: usize, last: usize, keep: &mut [bool]) {
+    keep[last] = true;
+    if last <= first + 1 {
+        return;
+    }
+    let (a, b) = (points[first], points[last]);
+    let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+    let distance = |p: Point| {
+        if length == 0.0 {
+            ((p.0 - a.0).powi(2) + (p.1 - a.1).powi(2)).sqrt()
+        } else {
+            ((b.0 - a.0) * (a.1 - p.1) - (a.0 - p.0) * (b.1 - a.1)).abs() / length
+        }
+    };
+    let (farthest, max) = (first + 1..last)
+        .map(|i| (i, distance(points[i])))
+        .fold((first, 0.0), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+    if max > TOLERANCE {
+        douglas_peucker(points, first, farthest, keep);
+        douglas_peucker(points, farthest, last, keep);
+    }
+}
+
+// Shoelace formula, unsigned
+fn area(ring: &[(i64, i64)]) -> f64 {
+    let twice: i64 = (0..ring.len())
+        .map(|i| {
+            let (x1, y1) = ring[i];
+            let (x2, y2) = ring[(i + 1) % ring.len()];
+            x1 * y2 - x2 * y1
+        })
+        .sum();
+    twice.abs() as f64 / 2.0
+}
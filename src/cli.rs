@@ -0,0 +1,431 @@
+use crate::data::{find_prefecture, Prefecture};
+use crate::import::{import_csv, import_keikenchi};
+use crate::keikenchi;
+use crate::settings::{load_settings, save_settings};
+use crate::storage::{self, list_backups, list_profiles, restore_backup, validate_profile_name};
+use crate::JTermApp;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "\
+Usage:
+  jterm                                   Start the interactive UI
+  jterm set <prefecture> <level>          Record a level (0-5) for today
+  jterm get <prefecture>                  Show a prefecture's level and visits
+  jterm stats [--json]                    Print travel statistics
+  jterm export --format csv|json|svg|png|keikenchi --output <path>
+                                          Write an export file; svg and png
+                                          draw the colored progress map
+  jterm import [--keikenchi] <file>       Merge levels from a CSV export, or
+                                          from shared 経県値 text (- for stdin)
+  jterm share                             Print the 経県値 share text
+  jterm restore [<n>]                     List backups, or restore backup <n>
+  jterm profiles                          List profiles
+  jterm profiles default <name>           Use <name> when --profile is not given
+  jterm help                              Show this message
+
+Options:
+  --profile <name>                        Use the progress saved under
+                                          ~/.jterm/profiles/<name>/
+
+<prefecture> may be an English or Japanese name, a JIS number (13) or an
+ISO code (JP-13).";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    Svg,
+    Png,
+    Keikenchi,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Svg => "svg",
+            Self::Png => "png",
+            Self::Keikenchi => "txt",
+        }
+    }
+
+    /// Name shown in export prompts and messages.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Json => "JSON",
+            Self::Csv => "CSV",
+            Self::Svg => "SVG",
+            Self::Png => "PNG",
+            Self::Keikenchi => "経県値 text",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "svg" => Ok(Self::Svg),
+            "png" => Ok(Self::Png),
+            "keikenchi" | "kkn" => Ok(Self::Keikenchi),
+            _ => Err(format!("unknown export format '{}', expected csv, json, svg, png or keikenchi", value)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Tui,
+    Help,
+    Set { prefecture: String, level: u8 },
+    Get { prefecture: String },
+    Stats { json: bool },
+    Export { format: ExportFormat, output: PathBuf },
+    Import { path: PathBuf, keikenchi: bool },
+    Share,
+    Restore { selection: Option<String> },
+    Profiles { default: Option<String> },
+}
+
+/// A parsed command line: the command and the `--profile` it names, if any.
+#[derive(Debug)]
+pub struct Invocation {
+    pub command: Command,
+    pub profile: Option<String>,
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Invocation, String> {
+    // --profile may come before or after the command
+    let mut args: Vec<String> = args.into_iter().collect();
+    let mut profile = None;
+    while let Some(i) = args.iter().position(|a| a == "--profile" || a.starts_with("--profile=")) {
+        let option = args.remove(i);
+        let value = match option.strip_prefix("--profile=") {
+            Some(value) => value.to_string(),
+            None if i < args.len() => args.remove(i),
+            None => return Err("--profile needs a value".to_string()),
+        };
+        profile = Some(value);
+    }
+
+    Ok(Invocation { command: parse_command(&args)?, profile })
+}
+
+fn parse_command(args: &[String]) -> Result<Command, String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Ok(Command::Tui);
+    };
+
+    let positional = |count: usize| -> Result<&[String], String> {
+        if rest.len() == count {
+            Ok(rest)
+        } else {
+            Err(format!("'{}' expects {} argument(s), got {}", command, count, rest.len()))
+        }
+    };
+
+    match command.as_str() {
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        "set" => {
+            let args = positional(2)?;
+            let level = match args[1].parse::<u8>() {
+                Ok(level @ 0..=5) => level,
+                _ => return Err(format!("level must be a number from 0 to 5, got '{}'", args[1])),
+            };
+            Ok(Command::Set { prefecture: args[0].clone(), level })
+        }
+        "get" => Ok(Command::Get { prefecture: positional(1)?[0].clone() }),
+        "stats" => match rest {
+            [] => Ok(Command::Stats { json: false }),
+            [flag] if flag == "--json" => Ok(Command::Stats { json: true }),
+            _ => Err("'stats' only accepts --json".to_string()),
+        },
+        "export" => {
+            let mut format = None;
+            let mut output = None;
+            let mut options = rest.iter();
+            while let Some(option) = options.next() {
+                let mut value = || {
+                    options
+                        .next()
+                        .ok_or_else(|| format!("{} needs a value", option))
+                };
+                match option.as_str() {
+                    "--format" | "-f" => format = Some(ExportFormat::parse(value()?)?),
+                    "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+                    _ => return Err(format!("unknown option '{}' for export", option)),
+                }
+            }
+            Ok(Command::Export {
+                format: format.ok_or("export needs --format csv|json|svg|png|keikenchi")?,
+                output: output.ok_or("export needs --output <path>")?,
+            })
+        }
+        "import" => match rest {
+            [flag, path] if flag == "--keikenchi" => Ok(Command::Import { path: PathBuf::from(path), keikenchi: true }),
+            [path] => Ok(Command::Import { path: PathBuf::from(path), keikenchi: false }),
+            _ => Err("usage: jterm import [--keikenchi] <file>".to_string()),
+        },
+        "share" => {
+            positional(0)?;
+            Ok(Command::Share)
+        }
+        "restore" => match rest {
+            [] => Ok(Command::Restore { selection: None }),
+            [selection] => Ok(Command::Restore { selection: Some(selection.clone()) }),
+            _ => Err("'restore' takes at most one argument".to_string()),
+        },
+        "profiles" => match rest {
+            [] => Ok(Command::Profiles { default: None }),
+            [action, name] if action == "default" => Ok(Command::Profiles { default: Some(name.clone()) }),
+            _ => Err("usage: jterm profiles [default <name>]".to_string()),
+        },
+        _ => Err(format!("unknown command '{}'", command)),
+    }
+}
+
+pub fn run(command: Command, profile: &str) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Tui => Ok(()),
+        Command::Help => {
+            writeln!(io::stdout().lock(), "{}", USAGE)?;
+            Ok(())
+        }
+        Command::Set { prefecture, level } => run_set(profile, &prefecture, level),
+        Command::Get { prefecture } => run_get(profile, &prefecture),
+        Command::Stats { json } => run_stats(profile, json),
+        Command::Export { format, output } => run_export(profile, format, &output),
+        Command::Import { path, keikenchi } => run_import(profile, &path, keikenchi),
+        Command::Share => {
+            let app = JTermApp::new(profile)?;
+            write!(io::stdout().lock(), "{}", app.keikenchi_share_text())?;
+            Ok(())
+        }
+        Command::Restore { selection } => run_restore(profile, selection.as_deref()),
+        Command::Profiles { default } => run_profiles(profile, default.as_deref()),
+    }
+}
+
+fn lookup<'a>(app: &'a JTermApp, query: &str) -> Result<&'a Prefecture, String> {
+    find_prefecture(&app.prefectures, query).ok_or_else(|| format!("no prefecture matches '{}'", query))
+}
+
+fn run_set(profile: &str, query: &str, level: u8) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    let mut app = JTermApp::new(profile)?;
+    let prefecture = lookup(&app, query)?.clone();
+
+    let today = chrono::Local::now().date_naive();
+    if let Err(earlier) = app.user_progress.log_level(&prefecture.iso_code, level, today) {
+        return Err(format!(
+            "{} stays at level {}: an earlier visit was level {}. Edit or delete it in the TUI's journal, \
+             or set 0 to clear its visits",
+            prefecture.name_en,
+            app.get_prefecture_level(&prefecture.iso_code),
+            earlier
+        )
+        .into());
+    }
+    app.save_progress()?;
+
+    let level = app.get_prefecture_level(&prefecture.iso_code);
+    writeln!(
+        out,
+        "{} ({}): Level {} - {} ({})",
+        prefecture.name_en,
+        prefecture.name_jp,
+        level,
+        JTermApp::get_level_text(level),
+        keikenchi::level_name(level)
+    )?;
+    Ok(())
+}
+
+fn run_get(profile: &str, query: &str) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    let app = JTermApp::new(profile)?;
+    let prefecture = lookup(&app, query)?;
+    let level = app.get_prefecture_level(&prefecture.iso_code);
+
+    writeln!(
+        out,
+        "{} ({}) {}: Level {} - {} ({})",
+        prefecture.name_en,
+        prefecture.name_jp,
+        prefecture.iso_code,
+        level,
+        JTermApp::get_level_text(level),
+        keikenchi::level_name(level)
+    )?;
+    let tags = app.user_progress.tags_for(&prefecture.iso_code);
+    if !tags.is_empty() {
+        writeln!(out, "  Tags: {}", tags.join(", "))?;
+    }
+    for visit in app.user_progress.visits_for(&prefecture.iso_code) {
+        let mut line = format!("  {}  Level {}", visit.date_label(), visit.level);
+        if !visit.companions.is_empty() {
+            line.push_str(&format!("  with {}", visit.companions.join(", ")));
+        }
+        if !visit.notes.is_empty() {
+            line.push_str(&format!("  {}", visit.notes));
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+fn run_stats(profile: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    let app = JTermApp::new(profile)?;
+
+    if json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&app.stats_json())?)?;
+        return Ok(());
+    }
+
+    let stats = app.calculate_stats();
+    let visited = stats.total_prefectures - stats.level_counts[0];
+    writeln!(
+        out,
+        "Visited: {} / {} ({}%)",
+        visited,
+        stats.total_prefectures,
+        (visited as f64 / stats.total_prefectures as f64 * 100.0) as u32
+    )?;
+    writeln!(out, "Total score (経県値): {} / {}", stats.total_score, stats.total_prefectures * 5)?;
+
+    writeln!(out, "\nLevels:")?;
+    for level in (0..=5).rev() {
+        writeln!(
+            out,
+            "  {} {:<17} {:>3}  {}",
+            level,
+            JTermApp::get_level_text(level),
+            stats.level_counts[level as usize],
+            keikenchi::level_name(level)
+        )?;
+    }
+
+    writeln!(out, "\nRegions:")?;
+    for region in &app.regions {
+        if let Some((visited, total)) = stats.region_stats.get(&region.name) {
+            writeln!(out, "  {:<10} {:>2}/{:<2}", region.name, visited, total)?;
+        }
+    }
+    Ok(())
+}
+
+fn run_export(profile: &str, format: ExportFormat, output: &Path) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    let app = JTermApp::new(profile)?;
+    app.export(format, output)?;
+    writeln!(out, "Exported to {}", output.display())?;
+    Ok(())
+}
+
+fn run_import(profile: &str, path: &Path, keikenchi: bool) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    let mut app = JTermApp::new(profile)?;
+    let contents = if path == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?
+    };
+    let import = if keikenchi { import_keikenchi } else { import_csv };
+    let report = import(&app.prefectures, &mut app.user_progress, &contents)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    if !report.updated.is_empty() {
+        app.save_progress()?;
+    }
+
+    writeln!(out, "Updated {} prefecture(s), {} unchanged", report.updated.len(), report.unchanged)?;
+    for (name, level) in &report.updated {
+        writeln!(out, "  + {} (level {})", name, level)?;
+    }
+    if !report.kept_higher.is_empty() {
+        writeln!(out, "Kept the higher recorded level for: {}", report.kept_higher.join(", "))?;
+    }
+    if !report.unmatched.is_empty() {
+        let unit = if keikenchi { "line" } else { "row" };
+        writeln!(out, "Unmatched entries:")?;
+        for (number, label) in &report.unmatched {
+            writeln!(out, "  {} {}: {}", unit, number, label)?;
+        }
+    }
+    Ok(())
+}
+
+/// `jterm restore` lists the backup snapshots, `jterm restore <n>` restores one.
+fn run_restore(profile: &str, selection: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    let backups = list_backups(profile)?;
+
+    let Some(selection) = selection else {
+        if backups.is_empty() {
+            writeln!(out, "No backups found in ~/.jterm/profiles/{}/backups/", profile)?;
+            return Ok(());
+        }
+
+        writeln!(out, "Available backups (newest first):\n")?;
+        for (i, backup) in backups.iter().enumerate() {
+            let summary = storage::read_progress_file(&backup.path)
+                .map(|progress| format!("{} visits", progress.visits.len()))
+                .unwrap_or_else(|_| "unreadable".to_string());
+            writeln!(out, "  {:>2}  {}  ({})", i + 1, backup.created.format("%Y-%m-%d %H:%M:%S"), summary)?;
+        }
+        writeln!(out, "\nRun `jterm restore <number>` to restore one.")?;
+        return Ok(());
+    };
+
+    let backup = selection
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| backups.get(i))
+        .ok_or_else(|| format!("No backup numbered '{}'; run `jterm restore` to list them", selection))?;
+
+    let progress = restore_backup(profile, backup)?;
+    writeln!(
+        out,
+        "Restored progress from {} ({} visits)",
+        backup.created.format("%Y-%m-%d %H:%M:%S"),
+        progress.visits.len()
+    )?;
+    Ok(())
+}
+
+fn run_profiles(active: &str, new_default: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    let mut settings = load_settings()?;
+
+    if let Some(name) = new_default {
+        validate_profile_name(name)?;
+        settings.default_profile = Some(name.to_string());
+        save_settings(&settings)?;
+        writeln!(out, "Default profile is now '{}'", name)?;
+        return Ok(());
+    }
+
+    let default = settings.default_profile.as_deref().unwrap_or(storage::DEFAULT_PROFILE);
+    let mut profiles = list_profiles()?;
+    for name in [active, default] {
+        if !profiles.iter().any(|p| p == name) {
+            profiles.push(name.to_string());
+        }
+    }
+    profiles.sort();
+
+    for name in &profiles {
+        let marker = if name == active { "*" } else { " " };
+        let visits = storage::read_user_progress(name)
+            .map(|progress| format!("{} visits", progress.visits.len()))
+            .unwrap_or_else(|e| format!("unreadable: {}", e));
+        let note = if name == default { ", default" } else { "" };
+        writeln!(out, "{} {} ({}{})", marker, name, visits, note)?;
+    }
+    Ok(())
+}
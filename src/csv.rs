@@ -0,0 +1,116 @@
+// Minimal RFC 4180 reader and writer, enough for the export/import columns.
+
+/// Append one record, quoting fields that contain a comma, quote or line
+/// break. Records end with CRLF as the RFC specifies.
+pub fn write_record<S: AsRef<str>>(out: &mut String, fields: &[S]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let field = field.as_ref();
+        if field.contains([',', '"', '\r', '\n']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push_str("\r\n");
+}
+
+/// Parse CSV text into records. Accepts CRLF or bare LF line endings and a
+/// leading UTF-8 byte order mark, as written by spreadsheet programs.
+pub fn parse(input: &str) -> Result<Vec<Vec<String>>, String> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line += 1;
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err(format!("line {}: unterminated quoted field", line));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    // Blank lines carry no data
+    records.retain(|r| !(r.len() == 1 && r[0].is_empty()));
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|f| f.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn quoted_fields_keep_commas_quotes_and_line_breaks() {
+        let input = "Name,Notes\nKyoto,\"Temples, shrines\"\nNara,\"The \"\"deer\"\" park\nand Todai-ji\"\n";
+        let expected = records(&[
+            &["Name", "Notes"],
+            &["Kyoto", "Temples, shrines"],
+            &["Nara", "The \"deer\" park\nand Todai-ji"],
+        ]);
+        assert_eq!(parse(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn accepts_crlf_and_a_byte_order_mark() {
+        let input = "\u{feff}Code,Level\r\n13,4\r\n\r\n27,\r\n";
+        assert_eq!(parse(input).unwrap(), records(&[&["Code", "Level"], &["13", "4"], &["27", ""]]));
+    }
+
+    #[test]
+    fn written_records_read_back() {
+        let rows = records(&[&["a,b", "say \"hi\"", "two\r\nlines", "", "plain"]]);
+        let mut out = String::new();
+        write_record(&mut out, &rows[0]);
+        assert_eq!(out, "\"a,b\",\"say \"\"hi\"\"\",\"two\r\nlines\",,plain\r\n");
+        assert_eq!(parse(&out).unwrap(), rows);
+    }
+
+    #[test]
+    fn an_unterminated_quote_is_an_error() {
+        assert_eq!(parse("a,b\n\"open,\nfield").unwrap_err(), "line 3: unterminated quoted field");
+    }
+}
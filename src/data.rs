@@ -0,0 +1,182 @@
+use crate::storage::get_data_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+
+// Bundled dataset. A file at ~/.jterm/data/prefectures.json with the same
+// layout takes precedence, so corrections don't need a rebuild.
+// Populations: 2020 national census (総務省統計局, 令和2年国勢調査).
+// Areas: GSI prefectural areas as of 2020-10-01, rounded to km².
+pub const BUNDLED_DATA: &str = include_str!("../data/prefectures.json");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prefecture {
+    pub code: u8,         // JIS X 0401 prefecture number, 1-47
+    pub iso_code: String, // ISO 3166-2:JP, e.g. "JP-13"; also the key progress is stored under
+    pub name_en: String,
+    pub name_jp: String,
+    #[serde(default)] // hiragana reading; older override files lack it
+    pub name_kana: String,
+    pub region: String,
+    pub map_pos: (u16, u16), // (row, col) position on ASCII map
+    pub map_char: String,    // character representation on map
+    pub capital: String,
+    pub population: u32,
+    pub area_km2: u32,
+}
+
+impl Prefecture {
+    /// Japanese name without the 都/府/県 suffix, e.g. "東京" for "東京都".
+    /// Hokkaido keeps its full name since 道 is part of it.
+    pub fn short_name_jp(&self) -> &str {
+        match self.name_jp.chars().last() {
+            Some(suffix @ ('都' | '府' | '県')) if self.name_jp.chars().count() > 2 => {
+                &self.name_jp[..self.name_jp.len() - suffix.len_utf8()]
+            }
+            _ => &self.name_jp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    pub name_jp: String,
+    pub emoji: String,
+}
+
+/// Regions and prefectures, both in north-to-south display order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeoData {
+    pub regions: Vec<Region>,
+    pub prefectures: Vec<Prefecture>,
+}
+
+pub fn load_geo_data() -> io::Result<GeoData> {
+    let override_file = get_data_dir()?.join("data").join("prefectures.json");
+
+    if override_file.exists() {
+        let contents = fs::read_to_string(&override_file)?;
+        parse_geo_data(&contents).map_err(|e| {
+            io::Error::new(e.kind(), format!("{}: {}", override_file.display(), e))
+        })
+    } else {
+        parse_geo_data(BUNDLED_DATA)
+    }
+}
+
+pub fn parse_geo_data(contents: &str) -> io::Result<GeoData> {
+    let data: GeoData = serde_json::from_str(contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    validate(&data)?;
+    Ok(data)
+}
+
+fn validate(data: &GeoData) -> io::Result<()> {
+    let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidData, message));
+
+    if data.prefectures.is_empty() {
+        return invalid("dataset contains no prefectures".to_string());
+    }
+
+    let regions: HashSet<&str> = data.regions.iter().map(|r| r.name.as_str()).collect();
+    let mut codes = HashSet::new();
+    let mut iso_codes = HashSet::new();
+    let mut names = HashSet::new();
+
+    for prefecture in &data.prefectures {
+        if !regions.contains(prefecture.region.as_str()) {
+            return invalid(format!("{} is in unknown region '{}'", prefecture.name_en, prefecture.region));
+        }
+        if !codes.insert(prefecture.code) {
+            return invalid(format!("duplicate prefecture code {}", prefecture.code));
+        }
+        if !iso_codes.insert(prefecture.iso_code.as_str()) {
+            return invalid(format!("duplicate ISO code '{}'", prefecture.iso_code));
+        }
+        if !names.insert(prefecture.name_en.as_str()) {
+            return invalid(format!("duplicate prefecture name '{}'", prefecture.name_en));
+        }
+        if prefecture.area_km2 == 0 {
+            return invalid(format!("{} has an area of 0 km²", prefecture.name_en));
+        }
+    }
+
+    Ok(())
+}
+
+/// Look up a prefecture by ISO code ("JP-13"), JIS number ("13"), English
+/// name with or without macrons ("Hyōgo", "hyogo-ken") or Japanese name
+/// ("兵庫県", "兵庫").
+pub fn find_prefecture<'a>(prefectures: &'a [Prefecture], query: &str) -> Option<&'a Prefecture> {
+    let query = query.trim();
+    if query.is_empty() {
+        return None;
+    }
+
+    if let Ok(code) = query.parse::<u8>() {
+        return prefectures.iter().find(|p| p.code == code);
+    }
+
+    let normalized = normalize_name(query);
+    prefectures.iter().find(|p| {
+        p.iso_code.eq_ignore_ascii_case(query)
+            || p.name_jp == query
+            || p.short_name_jp() == query
+            || normalize_name(&p.name_en) == normalized
+    })
+}
+
+/// Lowercase romaji with macrons folded, separators dropped and an
+/// administrative suffix ("-ken", " prefecture", ...) removed.
+pub fn normalize_name(name: &str) -> String {
+    let mut folded: String = name
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'ā' | 'â' => 'a',
+            'ī' | 'î' => 'i',
+            'ū' | 'û' => 'u',
+            'ē' | 'ê' => 'e',
+            'ō' | 'ô' => 'o',
+            _ => c,
+        })
+        .collect();
+
+    for suffix in [" prefecture", "-ken", " ken", "-fu", " fu", "-to", " to"] {
+        if let Some(stripped) = folded.strip_suffix(suffix) {
+            folded = stripped.to_string();
+            break;
+        }
+    }
+
+    folded.retain(|c| c.is_alphanumeric());
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_totals_match_national_figures() {
+        let prefectures = parse_geo_data(BUNDLED_DATA).unwrap().prefectures;
+        assert_eq!(prefectures.len(), 47);
+
+        let population: u64 = prefectures.iter().map(|p| u64::from(p.population)).sum();
+        assert_eq!(population, 126_146_099);
+        // Each area is rounded, so the sum may drift by a few km²
+        let area: u32 = prefectures.iter().map(|p| p.area_km2).sum();
+        assert!(area.abs_diff(377_975) <= 25, "total area {} km²", area);
+
+        let mut codes: Vec<u8> = prefectures.iter().map(|p| p.code).collect();
+        codes.sort();
+        assert_eq!(codes, (1..=47).collect::<Vec<u8>>());
+        let mut iso_codes: Vec<&str> = prefectures.iter().map(|p| p.iso_code.as_str()).collect();
+        iso_codes.sort();
+        let expected: Vec<String> = (1..=47).map(|code| format!("JP-{:02}", code)).collect();
+        assert_eq!(iso_codes, expected);
+    }
+}
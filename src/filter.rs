@@ -0,0 +1,141 @@
+use crate::data::Prefecture;
+use crate::progress::UserProgress;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// Which prefectures the list view shows and in what order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListOptions {
+    pub min_level: u8,
+    pub max_level: u8,
+    pub region: Option<String>,
+    pub tag: Option<String>,
+    pub sort: SortKey,
+    pub reverse: bool,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            min_level: 0,
+            max_level: 5,
+            region: None,
+            tag: None,
+            sort: SortKey::NorthToSouth,
+            reverse: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    NorthToSouth, // dataset order
+    Romaji,
+    Gojuon,
+    Level,
+    Population,
+    Area,
+    Density,
+    LastVisit,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 8] = [
+        SortKey::NorthToSouth,
+        SortKey::Romaji,
+        SortKey::Gojuon,
+        SortKey::Level,
+        SortKey::Population,
+        SortKey::Area,
+        SortKey::Density,
+        SortKey::LastVisit,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::NorthToSouth => "north to south",
+            SortKey::Romaji => "name (A-Z)",
+            SortKey::Gojuon => "name (あいうえお)",
+            SortKey::Level => "level",
+            SortKey::Population => "population",
+            SortKey::Area => "area",
+            SortKey::Density => "density",
+            SortKey::LastVisit => "last visit",
+        }
+    }
+}
+
+impl ListOptions {
+    /// The `indices` that pass the level, region and tag filters, in the
+    /// order given.
+    pub fn filter(&self, prefectures: &[Prefecture], progress: &UserProgress, indices: Vec<usize>) -> Vec<usize> {
+        indices
+            .into_iter()
+            .filter(|&index| {
+                let prefecture = &prefectures[index];
+                let level = progress.level(&prefecture.iso_code);
+                (self.min_level..=self.max_level).contains(&level)
+                    && self.region.as_ref().is_none_or(|region| *region == prefecture.region)
+                    && self
+                        .tag
+                        .as_ref()
+                        .is_none_or(|tag| progress.tags_for(&prefecture.iso_code).contains(tag))
+            })
+            .collect()
+    }
+
+    /// Order `indices` by `sort`. Numbers and dates go largest and newest
+    /// first, names alphabetically; ties keep the order they came in.
+    pub fn sort(&self, prefectures: &[Prefecture], progress: &UserProgress, indices: &mut [usize]) {
+        indices.sort_by(|&a, &b| {
+            let (a, b) = (&prefectures[a], &prefectures[b]);
+            let order = match self.sort {
+                SortKey::NorthToSouth => Ordering::Equal,
+                SortKey::Romaji => a.name_en.cmp(&b.name_en),
+                SortKey::Gojuon => a.name_kana.cmp(&b.name_kana),
+                SortKey::Level => progress.level(&b.iso_code).cmp(&progress.level(&a.iso_code)),
+                SortKey::Population => b.population.cmp(&a.population),
+                SortKey::Area => b.area_km2.cmp(&a.area_km2),
+                SortKey::Density => density(b).total_cmp(&density(a)),
+                SortKey::LastVisit => progress.last_visit(&b.iso_code).cmp(&progress.last_visit(&a.iso_code)),
+            };
+            if self.reverse { order.reverse() } else { order }
+        });
+        // Reversing north to south has no key to compare by
+        if self.reverse && self.sort == SortKey::NorthToSouth {
+            indices.reverse();
+        }
+    }
+
+    /// Short summary for the list title, e.g. "level ≥3 · Kansai · by
+    /// population". Empty when nothing differs from the defaults.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        match (self.min_level, self.max_level) {
+            (0, 5) => {}
+            (0, 0) => parts.push("unvisited".to_string()),
+            (1, 5) => parts.push("visited".to_string()),
+            (min, max) if min == max => parts.push(format!("level {}", min)),
+            (min, 5) => parts.push(format!("level ≥{}", min)),
+            (0, max) => parts.push(format!("level ≤{}", max)),
+            (min, max) => parts.push(format!("level {}-{}", min, max)),
+        }
+        if let Some(region) = &self.region {
+            parts.push(region.clone());
+        }
+        if let Some(tag) = &self.tag {
+            parts.push(format!("#{}", tag));
+        }
+        if self.sort != SortKey::NorthToSouth || self.reverse {
+            parts.push(format!("by {}{}", self.sort.label(), if self.reverse { ", reversed" } else { "" }));
+        }
+        parts.join(" · ")
+    }
+}
+
+fn density(prefecture: &Prefecture) -> f64 {
+    f64::from(prefecture.population) / f64::from(prefecture.area_km2.max(1))
+}
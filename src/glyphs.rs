@@ -0,0 +1,102 @@
+use ratatui::buffer::Buffer;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// Whether the TUI draws emoji and Unicode symbols or sticks to ASCII.
+/// Many terminals, SSH sessions and screen readers get the width of emoji
+/// and box-drawing characters wrong, which shifts everything after them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GlyphMode {
+    #[default]
+    Auto, // ASCII if the environment looks like it can't draw emoji
+    Unicode,
+    Ascii,
+}
+
+impl GlyphMode {
+    pub fn is_ascii(self) -> bool {
+        match self {
+            GlyphMode::Auto => detect_ascii(),
+            GlyphMode::Unicode => false,
+            GlyphMode::Ascii => true,
+        }
+    }
+}
+
+// JTERM_ASCII=1 or 0 decides outright; otherwise the Linux console, dumb
+// terminals and non-UTF-8 locales get ASCII
+fn detect_ascii() -> bool {
+    if let Ok(value) = env::var("JTERM_ASCII") {
+        return !matches!(value.as_str(), "" | "0" | "false" | "no");
+    }
+    if matches!(env::var("TERM").as_deref(), Ok("linux" | "dumb" | "vt100" | "vt220")) {
+        return true;
+    }
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty());
+    locale.is_some_and(|locale| {
+        let locale = locale.to_lowercase();
+        !locale.contains("utf-8") && !locale.contains("utf8")
+    })
+}
+
+/// `text` without emoji, and without the space that followed each one.
+/// Kanji and kana are kept: they are reliably two cells wide.
+pub fn strip_emoji(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if is_emoji(c) {
+            while chars.peek().is_some_and(|&next| is_emoji(next) || next == ' ') {
+                chars.next();
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF // pictographs, emoticons, transport, colored squares
+        | 0x2600..=0x27BF // symbols and dingbats (❄ ❌)
+        | 0x2B00..=0x2BFF // ⬜ ⬛
+        | 0xFE0F          // emoji presentation selector
+        | 0x200D          // zero-width joiner
+    )
+}
+
+/// Swap the one-cell symbols already drawn into `buffer` for ASCII ones,
+/// so borders, bars, arrows and markers keep their width everywhere.
+pub fn asciify_buffer(buffer: &mut Buffer) {
+    for cell in buffer.content.iter_mut() {
+        if let Some(ascii) = ascii_symbol(cell.symbol()) {
+            cell.set_symbol(ascii);
+        }
+    }
+}
+
+fn ascii_symbol(symbol: &str) -> Option<&'static str> {
+    let ascii = match symbol {
+        "─" | "━" | "═" | "·" => "-",
+        "│" | "┃" | "║" => "|",
+        "╭" | "╮" | "╰" | "╯" | "┌" | "┐" | "└" | "┘" | "├" | "┤" | "┬" | "┴" | "┼" => "+",
+        "█" | "▓" => "#",
+        "▒" => ":",
+        "░" => ".",
+        "▏" => "_",
+        "►" | "▶" | "→" | "›" => ">",
+        "◀" | "←" | "‹" => "<",
+        "↑" => "^",
+        "↓" => "v",
+        "●" => "*",
+        "○" => "o",
+        "²" => "2",
+        _ => return None,
+    };
+    Some(ascii)
+}
@@ -0,0 +1,44 @@
+use crate::progress::UserProgress;
+
+/// Undo and redo stacks of journal snapshots for the running session. Each
+/// entry keeps the whole journal from the other side of a change, which is
+/// small enough that diffing isn't worth it.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+struct Change {
+    description: String, // e.g. "Set Tokyo (東京都) to level 4 (was 2)"
+    progress: UserProgress,
+}
+
+impl History {
+    /// Remember `before`, the journal as it was before the change
+    /// `description` describes. Anything that was undone can no longer be
+    /// redone.
+    pub fn record(&mut self, description: String, before: UserProgress) {
+        self.undo.push(Change { description, progress: before });
+        self.redo.clear();
+    }
+
+    /// Put back the journal from before the last change and return what the
+    /// change was, or None if there is nothing to undo.
+    pub fn undo(&mut self, current: &mut UserProgress) -> Option<String> {
+        step(&mut self.undo, &mut self.redo, current)
+    }
+
+    /// Reapply the last undone change.
+    pub fn redo(&mut self, current: &mut UserProgress) -> Option<String> {
+        step(&mut self.redo, &mut self.undo, current)
+    }
+}
+
+// Swap `current` with the top of `from` and keep what was current on `to`
+fn step(from: &mut Vec<Change>, to: &mut Vec<Change>, current: &mut UserProgress) -> Option<String> {
+    let change = from.pop()?;
+    let replaced = std::mem::replace(current, change.progress);
+    to.push(Change { description: change.description.clone(), progress: replaced });
+    Some(change.description)
+}
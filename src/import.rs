@@ -0,0 +1,217 @@
+use crate::csv;
+use crate::data::{find_prefecture, Prefecture};
+use crate::keikenchi;
+use crate::progress::{UserProgress, Visit};
+use chrono::NaiveDate;
+
+/// Outcome of merging a CSV file into the progress journal.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub updated: Vec<(String, u8)>, // (prefecture, new level)
+    pub unchanged: usize,
+    // Rows whose level is below the one already recorded; the journal is
+    // never lowered by an import
+    pub kept_higher: Vec<String>,
+    pub unmatched: Vec<(usize, String)>, // (row or line number, identifying text)
+}
+
+fn field(row: &[String], index: usize) -> &str {
+    row.get(index).map(|f| f.trim()).unwrap_or("")
+}
+
+/// Merge a CSV export back into `progress`. Rows are matched by the Code,
+/// Prefecture_EN or Prefecture_JP column, whichever resolves first. A row
+/// whose Level is higher than the current one adds a visit dated from its
+/// Last_Visit column. Nothing is changed if any row has an invalid level or
+/// date.
+pub fn import_csv(
+    prefectures: &[Prefecture],
+    progress: &mut UserProgress,
+    contents: &str,
+) -> Result<ImportReport, String> {
+    let records = csv::parse(contents)?;
+    let Some((header, rows)) = records.split_first() else {
+        return Err("file is empty".to_string());
+    };
+
+    let column = |name: &str| header.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let key_columns: Vec<usize> = ["Code", "Prefecture_EN", "Prefecture_JP"]
+        .into_iter()
+        .filter_map(column)
+        .collect();
+    if key_columns.is_empty() {
+        return Err("no Code, Prefecture_EN or Prefecture_JP column".to_string());
+    }
+    let level_column = column("Level").ok_or("no Level column")?;
+    let date_column = column("Last_Visit");
+    let notes_column = column("Notes");
+
+    // Validate every row before touching the journal
+    let mut matched = Vec::new();
+    let mut unmatched = Vec::new();
+    let mut errors = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let row_number = i + 2; // 1-based, after the header
+
+        let prefecture = key_columns
+            .iter()
+            .find_map(|&index| find_prefecture(prefectures, field(row, index)));
+        let Some(prefecture) = prefecture else {
+            let label = key_columns
+                .iter()
+                .map(|&index| field(row, index))
+                .find(|value| !value.is_empty())
+                .unwrap_or("(blank)");
+            unmatched.push((row_number, label.to_string()));
+            continue;
+        };
+
+        let level = match field(row, level_column).parse::<u8>() {
+            Ok(level @ 0..=5) => level,
+            _ => {
+                errors.push(format!(
+                    "row {}: level must be a number from 0 to 5, got '{}'",
+                    row_number,
+                    field(row, level_column)
+                ));
+                continue;
+            }
+        };
+
+        let date = match date_column.map(|index| field(row, index)).unwrap_or("") {
+            "" => None,
+            value => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                Ok(date) => Some(date),
+                Err(_) => {
+                    errors.push(format!("row {}: invalid date '{}', expected YYYY-MM-DD", row_number, value));
+                    continue;
+                }
+            },
+        };
+
+        let notes = notes_column.map(|index| field(row, index)).unwrap_or("");
+        matched.push((prefecture, level, date, notes));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    Ok(merge(progress, matched, unmatched))
+}
+
+/// Merge shared 経県値 text (see `keikenchi::parse`) into `progress`, with
+/// the same rules as a CSV import. The new visits are undated.
+pub fn import_keikenchi(
+    prefectures: &[Prefecture],
+    progress: &mut UserProgress,
+    contents: &str,
+) -> Result<ImportReport, String> {
+    let parsed = keikenchi::parse(prefectures, contents)?;
+    let rows = parsed.levels.into_iter().map(|(p, level)| (p, level, None, "")).collect();
+    Ok(merge(progress, rows, parsed.unmatched))
+}
+
+// Levels are only ever raised: a row above the current level adds a visit,
+// anything else is counted and left alone
+fn merge(
+    progress: &mut UserProgress,
+    rows: Vec<(&Prefecture, u8, Option<NaiveDate>, &str)>,
+    unmatched: Vec<(usize, String)>,
+) -> ImportReport {
+    let mut report = ImportReport { unmatched, ..Default::default() };
+    for (prefecture, level, date, notes) in rows {
+        let current = progress.level(&prefecture.iso_code);
+        if level > current {
+            let mut visit = Visit::new(&prefecture.iso_code, level, date);
+            visit.notes = notes.to_string();
+            progress.visits.push(visit);
+            report.updated.push((prefecture.name_en.clone(), level));
+        } else if level < current {
+            report.kept_higher.push(prefecture.name_en.clone());
+        } else {
+            report.unchanged += 1;
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{parse_geo_data, BUNDLED_DATA};
+
+    fn prefectures() -> Vec<Prefecture> {
+        parse_geo_data(BUNDLED_DATA).unwrap().prefectures
+    }
+
+    fn date(text: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()
+    }
+
+    #[test]
+    fn raises_levels_with_the_row_date_and_notes() {
+        let prefectures = prefectures();
+        let mut progress = UserProgress::default();
+        let csv = "Code,Prefecture_EN,Level,Last_Visit,Notes\r\nJP-26,Kyoto,4,2024-05-03,\"Temples, shrines\"\r\n";
+        let report = import_csv(&prefectures, &mut progress, csv).unwrap();
+
+        assert_eq!(report.updated, vec![("Kyoto".to_string(), 4)]);
+        let mut expected = Visit::new("JP-26", 4, date("2024-05-03"));
+        expected.notes = "Temples, shrines".to_string();
+        assert_eq!(progress.visits, vec![expected]);
+    }
+
+    #[test]
+    fn never_lowers_a_level() {
+        let prefectures = prefectures();
+        let mut progress = UserProgress::default();
+        progress.visits.push(Visit::new("JP-13", 5, None));
+        progress.visits.push(Visit::new("JP-27", 2, None));
+        let before = progress.clone();
+
+        let csv = "Prefecture_EN,Level\nTokyo,3\nOsaka,2\nTokyo,0\n";
+        let report = import_csv(&prefectures, &mut progress, csv).unwrap();
+        assert_eq!(progress, before);
+        assert_eq!(report.kept_higher, vec!["Tokyo".to_string(), "Tokyo".to_string()]);
+        assert_eq!(report.unchanged, 1);
+        assert!(report.updated.is_empty());
+    }
+
+    #[test]
+    fn reports_rows_that_match_no_prefecture() {
+        let prefectures = prefectures();
+        let mut progress = UserProgress::default();
+        let csv = "Prefecture_EN,Prefecture_JP,Level\nAtlantis,,3\nHyogo,兵庫県,2\n,,1\n";
+        let report = import_csv(&prefectures, &mut progress, csv).unwrap();
+
+        assert_eq!(report.unmatched, vec![(2, "Atlantis".to_string()), (4, "(blank)".to_string())]);
+        assert_eq!(report.updated, vec![("Hyogo".to_string(), 2)]);
+    }
+
+    #[test]
+    fn rejects_levels_outside_0_to_5_without_changing_anything() {
+        let prefectures = prefectures();
+        let mut progress = UserProgress::default();
+        let csv = "Code,Level\n13,4\n27,6\n26,high\n";
+        let err = import_csv(&prefectures, &mut progress, csv).unwrap_err();
+
+        assert_eq!(
+            err,
+            "row 3: level must be a number from 0 to 5, got '6'\n\
+             row 4: level must be a number from 0 to 5, got 'high'"
+        );
+        assert_eq!(progress, UserProgress::default());
+    }
+
+    #[test]
+    fn rejects_malformed_dates_without_changing_anything() {
+        let prefectures = prefectures();
+        let mut progress = UserProgress::default();
+        let csv = "Code,Level,Last_Visit\n13,4,2024-05-03\n27,3,03/05/2024\n";
+        let err = import_csv(&prefectures, &mut progress, csv).unwrap_err();
+
+        assert_eq!(err, "row 3: invalid date '03/05/2024', expected YYYY-MM-DD");
+        assert_eq!(progress, UserProgress::default());
+    }
+}
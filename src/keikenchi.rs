@@ -0,0 +1,212 @@
+// 経県値 (keikenchi): the Japanese scheme jterm's 0-5 levels follow. A
+// prefecture scores its level in points, so the total runs from 0 to 235.
+
+use crate::data::{find_prefecture, Prefecture};
+
+/// Japanese name of a level, as used on 経県値 maps.
+pub fn level_name(level: u8) -> &'static str {
+    match level {
+        0 => "未踏",
+        1 => "通過した",
+        2 => "降り立った",
+        3 => "訪れた",
+        4 => "泊まった",
+        5 => "住んだ",
+        _ => "不明",
+    }
+}
+
+/// Level for a name in shared text. Older shares use 歩いた and 接地した
+/// for levels 3 and 2.
+fn parse_level_name(name: &str) -> Option<u8> {
+    match name {
+        "住んだ" => Some(5),
+        "泊まった" => Some(4),
+        "訪れた" | "歩いた" => Some(3),
+        "降り立った" | "接地した" => Some(2),
+        "通過した" => Some(1),
+        "未踏" | "行ったことがない" | "行ったことない" => Some(0),
+        _ => None,
+    }
+}
+
+/// The 経県値 total: the sum of every prefecture's level.
+pub fn total(levels: impl IntoIterator<Item = u8>) -> u32 {
+    levels.into_iter().map(u32::from).sum()
+}
+
+/// Share text listing prefectures by level, followed by the 47-digit code
+/// (levels in JIS order) that `parse` reads back.
+pub fn share_text(prefectures: &[Prefecture], level_of: impl Fn(&Prefecture) -> u8) -> String {
+    let levels: Vec<(&Prefecture, u8)> = prefectures.iter().map(|p| (p, level_of(p))).collect();
+    let visited = levels.iter().filter(|(_, level)| *level > 0).count();
+
+    let mut text = format!(
+        "経県値 {}点 / {}点（{}/{}都道府県）\n",
+        total(levels.iter().map(|(_, level)| *level)),
+        prefectures.len() * 5,
+        visited,
+        prefectures.len()
+    );
+    for level in (1..=5).rev() {
+        let names: Vec<&str> = levels
+            .iter()
+            .filter(|(_, l)| *l == level)
+            .map(|(p, _)| p.short_name_jp())
+            .collect();
+        if !names.is_empty() {
+            text.push_str(&format!("{}({}): {}\n", level_name(level), level, names.join("、")));
+        }
+    }
+
+    let mut by_code = levels.clone();
+    by_code.sort_by_key(|(p, _)| p.code);
+    let code: String = by_code.iter().map(|(_, level)| char::from(b'0' + level)).collect();
+    text.push_str(&format!("#経県値 {}\n", code));
+    text
+}
+
+pub struct Parsed<'a> {
+    pub levels: Vec<(&'a Prefecture, u8)>,
+    pub unmatched: Vec<(usize, String)>, // (line number, name)
+}
+
+/// Read 経県値 progress from shared text. A run of 47 digits (a share code
+/// or the payload of a map URL) is used when present; otherwise lines like
+/// "泊まった: 京都、大阪" or "京都府 4" are read.
+pub fn parse<'a>(prefectures: &'a [Prefecture], input: &str) -> Result<Parsed<'a>, String> {
+    if let Some(code) = find_code(input, prefectures.len()) {
+        let mut levels = Vec::new();
+        for (i, digit) in code.bytes().enumerate() {
+            let jis = i as u8 + 1;
+            let prefecture = prefectures
+                .iter()
+                .find(|p| p.code == jis)
+                .ok_or_else(|| format!("no prefecture with JIS number {}", jis))?;
+            levels.push((prefecture, digit - b'0'));
+        }
+        return Ok(Parsed { levels, unmatched: Vec::new() });
+    }
+
+    let mut parsed = Parsed { levels: Vec::new(), unmatched: Vec::new() };
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        let Some((left, right)) = line.split_once([':', '：']).or_else(|| line.trim().rsplit_once(' ')) else {
+            continue;
+        };
+
+        // "泊まった(4): 京都、大阪"
+        if let Some(level) = category_level(left) {
+            for name in right.split(['、', ',', '，', '・', '/', ' ', '　']).filter(|n| !n.trim().is_empty()) {
+                match find_prefecture(prefectures, name) {
+                    Some(prefecture) => parsed.levels.push((prefecture, level)),
+                    None => parsed.unmatched.push((line_number, name.trim().to_string())),
+                }
+            }
+            continue;
+        }
+
+        // "京都府: 泊まった" or "京都府 4"
+        let level = right.trim().parse::<u8>().ok().filter(|l| *l <= 5).or_else(|| category_level(right));
+        if let (Some(prefecture), Some(level)) = (find_prefecture(prefectures, left), level) {
+            parsed.levels.push((prefecture, level));
+        }
+    }
+
+    if parsed.levels.is_empty() && parsed.unmatched.is_empty() {
+        return Err("no 経県値 levels found; expected a 47-digit code or lines like \"泊まった: 京都、大阪\"".to_string());
+    }
+    Ok(parsed)
+}
+
+// "泊まった", "泊まった(4)" or "泊まった 4点" -> 4
+fn category_level(text: &str) -> Option<u8> {
+    let name = text
+        .trim()
+        .split(['(', '（', ' ', '　'])
+        .next()
+        .unwrap_or("");
+    parse_level_name(name)
+}
+
+// First run of exactly `len` digits 0-5, e.g. the payload of a map URL
+fn find_code(input: &str, len: usize) -> Option<&str> {
+    input
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|run| run.len() == len && run.bytes().all(|b| (b'0'..=b'5').contains(&b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{parse_geo_data, BUNDLED_DATA};
+
+    fn prefectures() -> Vec<Prefecture> {
+        parse_geo_data(BUNDLED_DATA).unwrap().prefectures
+    }
+
+    // (ISO code, level) pairs, sorted so the order of the input doesn't matter
+    fn levels(parsed: &Parsed) -> Vec<(String, u8)> {
+        let mut levels: Vec<(String, u8)> = parsed.levels.iter().map(|(p, l)| (p.iso_code.clone(), *l)).collect();
+        levels.sort();
+        levels
+    }
+
+    #[test]
+    fn share_text_reads_back() {
+        let prefectures = prefectures();
+        let level_of = |p: &Prefecture| p.code % 6;
+        let parsed = parse(&prefectures, &share_text(&prefectures, level_of)).unwrap();
+
+        let mut expected: Vec<(String, u8)> = prefectures.iter().map(|p| (p.iso_code.clone(), level_of(p))).collect();
+        expected.sort();
+        assert_eq!(levels(&parsed), expected);
+        assert!(parsed.unmatched.is_empty());
+    }
+
+    #[test]
+    fn reads_a_code_inside_a_url() {
+        let prefectures = prefectures();
+        let code = format!("4{}5", "0".repeat(45));
+        let parsed = parse(&prefectures, &format!("https://example.com/map?code={}&v=2", code)).unwrap();
+
+        let visited: Vec<(String, u8)> = levels(&parsed).into_iter().filter(|(_, l)| *l > 0).collect();
+        assert_eq!(visited, vec![("JP-01".to_string(), 4), ("JP-47".to_string(), 5)]);
+        assert_eq!(parsed.levels.len(), 47);
+    }
+
+    #[test]
+    fn reads_category_lines() {
+        let prefectures = prefectures();
+        let parsed = parse(&prefectures, "経県値 11点\n泊まった(4): 京都、大阪\n歩いた: 北海道\n").unwrap();
+        let expected = [("JP-01", 3), ("JP-26", 4), ("JP-27", 4)].map(|(code, level)| (code.to_string(), level));
+        assert_eq!(levels(&parsed), expected);
+    }
+
+    #[test]
+    fn reads_prefecture_level_lines() {
+        let prefectures = prefectures();
+        let parsed = parse(&prefectures, "京都府 4\n沖縄県: 住んだ\n").unwrap();
+        let expected = [("JP-26", 4), ("JP-47", 5)].map(|(code, level)| (code.to_string(), level));
+        assert_eq!(levels(&parsed), expected);
+    }
+
+    #[test]
+    fn reports_unknown_names_with_their_line() {
+        let prefectures = prefectures();
+        let parsed = parse(&prefectures, "住んだ(5): 東京\n通過した(1): 奈良、アトランティス\n").unwrap();
+        let expected = [("JP-13", 5), ("JP-29", 1)].map(|(code, level)| (code.to_string(), level));
+        assert_eq!(levels(&parsed), expected);
+        assert_eq!(parsed.unmatched, vec![(2, "アトランティス".to_string())]);
+    }
+
+    #[test]
+    fn ignores_codes_of_the_wrong_length() {
+        let prefectures = prefectures();
+        for len in [46, 48] {
+            assert!(parse(&prefectures, &"3".repeat(len)).is_err(), "{} digits", len);
+        }
+        // A code with a digit above 5 isn't one either
+        assert!(parse(&prefectures, &format!("6{}", "0".repeat(46))).is_err());
+    }
+}
@@ -0,0 +1,618 @@
+use crate::cli::ExportFormat;
+use crate::storage::get_data_dir;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+/// Everything a key can do outside of prompts and menus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Quit,
+    Help,
+    Palette,
+    Search,
+    Filter,
+    Tags,
+    Profiles,
+    NextTheme,
+    NextPalette,
+    TogglePatterns,
+    ToggleAscii,
+    Undo,
+    Redo,
+    MapView,
+    StatsView,
+    OverviewView,
+    JournalView,
+    Up,
+    Down,
+    Left,  // previous prefecture on the region map
+    Right, // next prefecture on the region map
+    Open,  // prefecture details, or edit the visit in the journal
+    Close,
+    LogVisit,
+    DeleteVisit,
+    SetLevel(u8),
+    Export(ExportFormat),
+}
+
+/// Setting levels 0-5, for help rows that list them together.
+pub const LEVELS: [Action; 6] = [
+    Action::SetLevel(0),
+    Action::SetLevel(1),
+    Action::SetLevel(2),
+    Action::SetLevel(3),
+    Action::SetLevel(4),
+    Action::SetLevel(5),
+];
+
+impl Action {
+    pub const ALL: [Action; 36] = [
+        Action::Quit,
+        Action::Help,
+        Action::Palette,
+        Action::Search,
+        Action::Filter,
+        Action::Tags,
+        Action::Profiles,
+        Action::NextTheme,
+        Action::NextPalette,
+        Action::TogglePatterns,
+        Action::ToggleAscii,
+        Action::Undo,
+        Action::Redo,
+        Action::MapView,
+        Action::StatsView,
+        Action::OverviewView,
+        Action::JournalView,
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Open,
+        Action::Close,
+        Action::LogVisit,
+        Action::DeleteVisit,
+        LEVELS[0],
+        LEVELS[1],
+        LEVELS[2],
+        LEVELS[3],
+        LEVELS[4],
+        LEVELS[5],
+        Action::Export(ExportFormat::Json),
+        Action::Export(ExportFormat::Csv),
+        Action::Export(ExportFormat::Svg),
+        Action::Export(ExportFormat::Png),
+        Action::Export(ExportFormat::Keikenchi),
+    ];
+
+    /// Name in keys.toml, e.g. "map_view" or "level_3".
+    pub fn name(self) -> String {
+        let name = match self {
+            Action::Quit => "quit",
+            Action::Help => "help",
+            Action::Palette => "palette",
+            Action::Search => "search",
+            Action::Filter => "filter",
+            Action::Tags => "tags",
+            Action::Profiles => "profiles",
+            Action::NextTheme => "next_theme",
+            Action::NextPalette => "next_palette",
+            Action::TogglePatterns => "toggle_patterns",
+            Action::ToggleAscii => "toggle_ascii",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::MapView => "map_view",
+            Action::StatsView => "stats_view",
+            Action::OverviewView => "overview_view",
+            Action::JournalView => "journal_view",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Open => "open",
+            Action::Close => "close",
+            Action::LogVisit => "log_visit",
+            Action::DeleteVisit => "delete_visit",
+            Action::Export(ExportFormat::Json) => "export_json",
+            Action::Export(ExportFormat::Csv) => "export_csv",
+            Action::Export(ExportFormat::Svg) => "export_svg",
+            Action::Export(ExportFormat::Png) => "export_png",
+            Action::Export(ExportFormat::Keikenchi) => "export_keikenchi",
+            Action::SetLevel(level) => return format!("level_{}", level),
+        };
+        name.to_string()
+    }
+
+    /// What the action does, as the command palette lists it.
+    pub fn label(self) -> String {
+        let label = match self {
+            Action::Quit => "Quit",
+            Action::Help => "Toggle help",
+            Action::Palette => "Command palette",
+            Action::Search => "Search prefectures",
+            Action::Filter => "Filter and sort the list",
+            Action::Tags => "Edit tags",
+            Action::Profiles => "Switch profile",
+            Action::NextTheme => "Next color theme",
+            Action::NextPalette => "Colorblind-safe level colors",
+            Action::TogglePatterns => "Level glyphs and patterns",
+            Action::ToggleAscii => "ASCII mode",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::MapView => "Toggle map view",
+            Action::StatsView => "Toggle stats view",
+            Action::OverviewView => "Toggle overview map",
+            Action::JournalView => "Toggle travel journal",
+            Action::Up => "Move up",
+            Action::Down => "Move down",
+            Action::Left => "Previous prefecture on the map",
+            Action::Right => "Next prefecture on the map",
+            Action::Open => "Show details or edit visit",
+            Action::Close => "Close details",
+            Action::LogVisit => "Log a visit with notes",
+            Action::DeleteVisit => "Delete visit",
+            Action::SetLevel(level) => return format!("Set level {}", level),
+            Action::Export(format) => return format!("Export {}", format.label()),
+        };
+        label.to_string()
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// A key with Ctrl or Alt held. Shift is part of the character, so "P"
+/// and "p" are different keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        let modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        let code = match code {
+            KeyCode::Char(c) if !modifiers.is_empty() => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        Key { code, modifiers }
+    }
+
+    fn from_event(event: KeyEvent) -> Key {
+        let key = Key::new(event.code, event.modifiers);
+        // Terminals send Ctrl-_ and Ctrl-/ as 0x1F, which crossterm reads
+        // as Ctrl-7
+        if key == ctrl('7') { ctrl('_') } else { key }
+    }
+}
+
+fn ch(c: char) -> Key {
+    Key::new(KeyCode::Char(c), KeyModifiers::NONE)
+}
+
+fn ctrl(c: char) -> Key {
+    Key::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+}
+
+fn alt(c: char) -> Key {
+    Key::new(KeyCode::Char(c), KeyModifiers::ALT)
+}
+
+fn code(code: KeyCode) -> Key {
+    Key::new(code, KeyModifiers::NONE)
+}
+
+/// Parses keys as written in keys.toml: a character ("q", "P", "#"), a
+/// name ("enter", "esc", "up", "space", "f1") and any "ctrl-"/"alt-"
+/// prefixes.
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Key, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        // The key itself may be "-", as in "-" or "ctrl--"
+        while let Some((prefix, key)) = rest.split_once('-')
+            && !key.is_empty()
+        {
+            modifiers |= match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "m" => KeyModifiers::ALT,
+                _ => return Err(format!("unknown modifier '{}' in '{}'", prefix, text)),
+            };
+            rest = key;
+        }
+
+        let name = rest.to_ascii_lowercase();
+        let key_code = match name.as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "space" => KeyCode::Char(' '),
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                        Some(n @ 1..=12) => KeyCode::F(n),
+                        _ => return Err(format!("unknown key '{}'", text)),
+                    },
+                }
+            }
+        };
+        Ok(Key::new(key_code, modifiers))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if !self.modifiers.is_empty() => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            KeyCode::Delete => write!(f, "Del"),
+            code => write!(f, "{}", code),
+        }
+    }
+}
+
+/// Starting points for keys.toml.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    #[default]
+    Default,
+    Vim,    // h/j/k/l move, ? for help
+    Emacs,  // Ctrl-P/N/B/F move, Ctrl-S search, Alt-X palette, Ctrl-G closes
+    Arrows, // only the arrow keys move, leaving j/k unbound
+}
+
+/// Which keys trigger which action. A key belongs to at most one action;
+/// an action may have several keys, or none.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<Key>)>, // in Action::ALL order
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::preset(Preset::Default)
+    }
+}
+
+impl Keymap {
+    pub fn preset(preset: Preset) -> Keymap {
+        let mut keymap = Keymap {
+            bindings: Action::ALL.into_iter().map(|action| (action, default_keys(action))).collect(),
+        };
+        let overrides = match preset {
+            Preset::Default => vec![],
+            Preset::Vim => vec![
+                (Action::Help, vec![ch('?'), code(KeyCode::F(1))]),
+                (Action::Left, vec![code(KeyCode::Left), ch('h')]),
+                (Action::Right, vec![code(KeyCode::Right), ch('l')]),
+            ],
+            Preset::Emacs => vec![
+                (Action::Quit, vec![ch('q'), ctrl('c')]),
+                (Action::Palette, vec![ch(':'), alt('x')]),
+                (Action::Search, vec![ch('/'), ctrl('s'), ctrl('r')]),
+                (Action::Undo, vec![ch('u'), ctrl('_')]),
+                (Action::Redo, vec![alt('_')]),
+                (Action::Up, vec![code(KeyCode::Up), ctrl('p')]),
+                (Action::Down, vec![code(KeyCode::Down), ctrl('n')]),
+                (Action::Left, vec![code(KeyCode::Left), ctrl('b')]),
+                (Action::Right, vec![code(KeyCode::Right), ctrl('f')]),
+                (Action::Close, vec![code(KeyCode::Esc), ctrl('g')]),
+            ],
+            Preset::Arrows => vec![
+                (Action::Up, vec![code(KeyCode::Up)]),
+                (Action::Down, vec![code(KeyCode::Down)]),
+            ],
+        };
+        for (action, keys) in overrides {
+            keymap.bind(action, keys);
+        }
+        keymap
+    }
+
+    /// The action `event` triggers, if any.
+    pub fn action(&self, event: KeyEvent) -> Option<Action> {
+        let key = Key::from_event(event);
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|&(action, _)| action)
+    }
+
+    pub fn keys(&self, action: Action) -> &[Key] {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .map_or(&[], |(_, keys)| keys)
+    }
+
+    pub fn is_bound(&self, actions: &[Action]) -> bool {
+        actions.iter().any(|&action| !self.keys(action).is_empty())
+    }
+
+    /// The keys for `actions` as help shows them: "↑/↓ or k/j" for
+    /// actions that go together, "0-5" for a run of characters, "u,
+    /// Ctrl-R/U" when they don't pair up, or "(unbound)".
+    pub fn hint(&self, actions: &[Action]) -> String {
+        let keys: Vec<&[Key]> = actions.iter().map(|&action| self.keys(action)).collect();
+        if !self.is_bound(actions) {
+            return "(unbound)".to_string();
+        }
+
+        let chars: Vec<char> = keys
+            .iter()
+            .filter_map(|keys| match keys {
+                [Key { code: KeyCode::Char(c), modifiers }] if modifiers.is_empty() => Some(*c),
+                _ => None,
+            })
+            .collect();
+        if actions.len() > 2
+            && chars.len() == actions.len()
+            && chars.windows(2).all(|pair| pair[1] as u32 == pair[0] as u32 + 1)
+        {
+            return format!("{}-{}", chars[0], chars[chars.len() - 1]);
+        }
+
+        // Pair the keys up only when every action has as many
+        if keys.iter().any(|k| k.len() != keys[0].len()) {
+            return keys
+                .iter()
+                .filter(|keys| !keys.is_empty())
+                .map(|keys| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>().join("/"))
+                .collect::<Vec<_>>()
+                .join(", ");
+        }
+        (0..keys[0].len())
+            .map(|i| {
+                keys.iter()
+                    .filter_map(|keys| keys.get(i))
+                    .map(|key| key.to_string())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect::<Vec<_>>()
+            .join(" or ")
+    }
+
+    // Give `keys` to `action`, taking them from whatever had them
+    fn bind(&mut self, action: Action, keys: Vec<Key>) {
+        for (bound, bound_keys) in &mut self.bindings {
+            if *bound == action {
+                *bound_keys = keys.clone();
+            } else {
+                bound_keys.retain(|key| !keys.contains(key));
+            }
+        }
+    }
+}
+
+fn default_keys(action: Action) -> Vec<Key> {
+    match action {
+        Action::Quit => vec![ch('q')],
+        Action::Help => vec![ch('h'), code(KeyCode::F(1))],
+        Action::Palette => vec![ch(':'), ctrl('p')],
+        Action::Search => vec![ch('/')],
+        Action::Filter => vec![ch('f')],
+        Action::Tags => vec![ch('#')],
+        Action::Profiles => vec![ch('P')],
+        Action::NextTheme => vec![ch('T')],
+        Action::NextPalette => vec![ch('C')],
+        Action::TogglePatterns => vec![ch('G')],
+        Action::ToggleAscii => vec![ch('A')],
+        Action::Undo => vec![ch('u')],
+        Action::Redo => vec![ctrl('r')],
+        Action::MapView => vec![ch('m')],
+        Action::StatsView => vec![ch('s')],
+        Action::OverviewView => vec![ch('w')],
+        Action::JournalView => vec![ch('v')],
+        Action::Up => vec![code(KeyCode::Up), ch('k')],
+        Action::Down => vec![code(KeyCode::Down), ch('j')],
+        Action::Left => vec![code(KeyCode::Left)],
+        Action::Right => vec![code(KeyCode::Right)],
+        Action::Open => vec![code(KeyCode::Enter)],
+        Action::Close => vec![code(KeyCode::Esc)],
+        Action::LogVisit => vec![ch('a')],
+        Action::DeleteVisit => vec![ch('d')],
+        Action::SetLevel(level) => vec![ch(char::from(b'0' + level))],
+        Action::Export(ExportFormat::Json) => vec![ch('e')],
+        Action::Export(ExportFormat::Csv) => vec![ch('x')],
+        Action::Export(ExportFormat::Svg) => vec![ch('g')],
+        Action::Export(ExportFormat::Png) => vec![ch('p')],
+        Action::Export(ExportFormat::Keikenchi) => vec![ch('t')],
+    }
+}
+
+// ~/.jterm/keys.toml: a preset, then the actions to rebind, e.g.
+//
+//     preset = "vim"
+//     [keys]
+//     redo = ["ctrl-r", "U"]
+//     map_view = "M"
+//
+// A key given here is taken from whatever action the preset gave it to;
+// an empty list unbinds the action.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeymapFile {
+    #[serde(default)]
+    preset: Preset,
+    #[serde(default)]
+    keys: BTreeMap<String, KeyList>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// The keymap from ~/.jterm/keys.toml, or the default one if there is no
+/// such file.
+pub fn load_keymap() -> io::Result<Keymap> {
+    let path = get_data_dir()?.join("keys.toml");
+    if !path.exists() {
+        return Ok(Keymap::default());
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    parse_keymap(&contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+}
+
+fn parse_keymap(contents: &str) -> Result<Keymap, String> {
+    let file: KeymapFile = toml::from_str(contents).map_err(|e| e.message().to_string())?;
+    let mut keymap = Keymap::preset(file.preset);
+
+    let mut claimed: Vec<(Key, Action)> = Vec::new();
+    for (name, keys) in file.keys {
+        let action = Action::from_name(&name).ok_or_else(|| format!("unknown action '{}'", name))?;
+        let keys = match keys {
+            KeyList::One(key) => vec![key],
+            KeyList::Many(keys) => keys,
+        };
+        let keys = keys.iter().map(|key| key.parse()).collect::<Result<Vec<Key>, _>>()?;
+        for &key in &keys {
+            if let Some((_, other)) = claimed.iter().find(|(claimed, _)| *claimed == key) {
+                return Err(format!("'{}' is bound to both {} and {}", key, other.name(), name));
+            }
+            claimed.push((key, action));
+        }
+        keymap.bind(action, keys);
+    }
+    Ok(keymap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &str) -> Key {
+        text.parse().unwrap()
+    }
+
+    fn event(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_characters_names_and_modifiers() {
+        assert_eq!(key("q"), ch('q'));
+        assert_eq!(key("P"), ch('P'));
+        assert_eq!(key("-"), ch('-'));
+        assert_eq!(key("space"), ch(' '));
+        assert_eq!(key("Enter"), code(KeyCode::Enter));
+        assert_eq!(key("pgdn"), code(KeyCode::PageDown));
+        assert_eq!(key("f12"), code(KeyCode::F(12)));
+
+        assert_eq!(key("ctrl-r"), ctrl('r'));
+        // Shift doesn't count once Ctrl or Alt is held
+        assert_eq!(key("Ctrl-R"), ctrl('r'));
+        assert_eq!(key("ctrl--"), ctrl('-'));
+        assert_eq!(key("m-x"), alt('x'));
+        assert_eq!(key("ctrl-alt-up"), Key::new(KeyCode::Up, KeyModifiers::CONTROL | KeyModifiers::ALT));
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_modifiers() {
+        assert_eq!("super-x".parse::<Key>().unwrap_err(), "unknown modifier 'super' in 'super-x'");
+        assert_eq!("ctrl-f13".parse::<Key>().unwrap_err(), "unknown key 'ctrl-f13'");
+        assert_eq!("qq".parse::<Key>().unwrap_err(), "unknown key 'qq'");
+    }
+
+    #[test]
+    fn displays_keys_as_help_shows_them() {
+        assert_eq!(key("ctrl-r").to_string(), "Ctrl-R");
+        assert_eq!(key("alt-_").to_string(), "Alt-_");
+        assert_eq!(key("up").to_string(), "↑");
+        assert_eq!(key("space").to_string(), "Space");
+    }
+
+    #[test]
+    fn events_find_their_action() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action(event(KeyCode::Char('P'), KeyModifiers::SHIFT)), Some(Action::Profiles));
+        assert_eq!(keymap.action(event(KeyCode::Char('p'), KeyModifiers::NONE)), Some(Action::Export(ExportFormat::Png)));
+        assert_eq!(keymap.action(event(KeyCode::Char('r'), KeyModifiers::CONTROL)), Some(Action::Redo));
+        assert_eq!(keymap.action(event(KeyCode::Char('z'), KeyModifiers::NONE)), None);
+    }
+
+    #[test]
+    fn keys_file_rebinds_on_top_of_a_preset() {
+        let keymap = parse_keymap(
+            r#"
+            preset = "vim"
+            [keys]
+            redo = ["ctrl-r", "U"]
+            profiles = "alt-p"
+            journal_view = []
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(keymap.keys(Action::Left), &[code(KeyCode::Left), ch('h')]);
+        assert_eq!(keymap.keys(Action::Redo), &[ctrl('r'), ch('U')]);
+        assert_eq!(keymap.keys(Action::Profiles), &[alt('p')]);
+        assert_eq!(keymap.action(event(KeyCode::Char('P'), KeyModifiers::SHIFT)), None);
+        assert!(!keymap.is_bound(&[Action::JournalView]));
+    }
+
+    #[test]
+    fn a_rebound_key_leaves_its_old_action() {
+        let keymap = parse_keymap("[keys]\nmap_view = \"s\"\n").unwrap();
+        assert_eq!(keymap.keys(Action::MapView), &[ch('s')]);
+        assert!(keymap.keys(Action::StatsView).is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_actions() {
+        let err = parse_keymap("[keys]\nteleport = \"t\"\n").unwrap_err();
+        assert_eq!(err, "unknown action 'teleport'");
+    }
+
+    #[test]
+    fn rejects_a_key_bound_to_two_actions() {
+        let err = parse_keymap("[keys]\nundo = \"z\"\nredo = [\"ctrl-r\", \"z\"]\n").unwrap_err();
+        assert_eq!(err, "'z' is bound to both redo and undo");
+    }
+
+    #[test]
+    fn rejects_unknown_presets_and_fields() {
+        assert!(parse_keymap("preset = \"dvorak\"\n").is_err());
+        assert!(parse_keymap("keymap = {}\n").is_err());
+    }
+}
@@ -1,64 +1,53 @@
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use chrono;
 use ratatui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
     symbols::border,
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
 use ratatui_image::{picker::Picker, StatefulImage, protocol::StatefulProtocol};
 
-// Flexoki Light theme colors
-#[allow(dead_code)]
-struct FlexokiTheme;
-
-#[allow(dead_code)]
-impl FlexokiTheme {
-    const BG: Color = Color::Rgb(252, 249, 243); // #fcf9f3
-    const FG: Color = Color::Rgb(16, 15, 13); // #100f0d
-    const UI: Color = Color::Rgb(215, 204, 183); // #d7ccb7
-    const UI2: Color = Color::Rgb(188, 174, 147); // #bcae93
-    const UI3: Color = Color::Rgb(162, 147, 118); // #a29376
-    const TX: Color = Color::Rgb(16, 15, 13); // #100f0d
-    const TX2: Color = Color::Rgb(87, 82, 74); // #57524a
-    const TX3: Color = Color::Rgb(162, 147, 118); // #a29376
-    const RE: Color = Color::Rgb(175, 75, 74); // #af4b4a
-    const OR: Color = Color::Rgb(188, 92, 51); // #bc5c33
-    const YE: Color = Color::Rgb(173, 135, 29); // #ad871d
-    const GR: Color = Color::Rgb(66, 130, 62); // #42823e
-    const CY: Color = Color::Rgb(36, 139, 142); // #248b8e
-    const BL: Color = Color::Rgb(72, 108, 166); // #486ca6
-    const PU: Color = Color::Rgb(137, 89, 168); // #8959a8
-    const MA: Color = Color::Rgb(204, 102, 153); // #cc6699
-}
-use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use unicode_width::UnicodeWidthStr;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Prefecture {
-    name_en: String,
-    name_jp: String,
-    region: String,
-    map_pos: (u16, u16), // (row, col) position on ASCII map
-    map_char: String,    // character representation on map
-    capital: String,
-    population: u32,
-    area_km2: u32,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct UserProgress {
-    prefecture_levels: HashMap<String, u8>, // prefecture name -> level (0-5)
-}
+use std::path::{Path, PathBuf};
+
+mod cli;
+mod csv;
+mod data;
+mod filter;
+mod glyphs;
+mod history;
+mod import;
+mod keymap;
+mod keikenchi;
+mod map;
+mod progress;
+mod search;
+mod settings;
+mod storage;
+mod theme;
+
+use cli::{Command, ExportFormat};
+use data::{load_geo_data, GeoData, Prefecture, Region};
+use filter::SortKey;
+use glyphs::{strip_emoji, GlyphMode};
+use history::History;
+use keymap::{load_keymap, Action, Keymap, LEVELS};
+use progress::{parse_date_range, UserProgress, Visit};
+use settings::{active_profile, load_settings, save_settings, Settings};
+use storage::{list_profiles, load_user_progress, save_user_progress, validate_profile_name};
+use theme::{builtin_themes, level_pattern, load_themes, Palette, Theme, ASCII_LEVEL_GLYPHS, LEVEL_GLYPHS};
 
 #[derive(Debug)]
 struct TravelStats {
@@ -68,102 +57,345 @@ struct TravelStats {
     region_stats: HashMap<String, (usize, usize)>, // region -> (visited, total)
 }
 
-impl Default for UserProgress {
-    fn default() -> Self {
+// Width in pixels the choropleth is rasterized at before ratatui-image
+// scales it into the terminal
+const MAP_IMAGE_WIDTH: u32 = 1200;
+// Width of the PNG export, large enough to share at full size
+const EXPORT_IMAGE_WIDTH: u32 = 1600;
+
+// Header of the CSV export; `jterm import` reads the same columns
+const CSV_COLUMNS: [&str; 11] = [
+    "Code",
+    "Prefecture_EN",
+    "Prefecture_JP",
+    "Region",
+    "Level",
+    "Experience",
+    "Capital",
+    "Population",
+    "Area_km2",
+    "Last_Visit",
+    "Notes",
+];
+
+/// Text of the region map view and where each prefecture row landed.
+struct RegionMap {
+    lines: Vec<ratatui::text::Line<'static>>,
+    rows: Vec<(usize, usize)>, // (line number, index into prefectures), in display order
+}
+
+impl RegionMap {
+    fn line_of(&self, prefecture_index: usize) -> Option<usize> {
+        self.rows
+            .iter()
+            .find(|(_, index)| *index == prefecture_index)
+            .map(|(line, _)| *line)
+    }
+}
+
+/// The screen shown in the main area. Every view except the journal shows
+/// the same selected prefecture.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+    List,
+    RegionMap,
+    Stats,
+    Overview,
+    Journal,
+}
+
+/// Where the last frame drew the clickable and scrollable parts of the
+/// current view, in terminal cells. The render functions fill this in for
+/// mouse hit-testing and to size scrolling to the real viewport.
+#[derive(Default)]
+struct ScreenAreas {
+    list: Rect,                        // inside of the prefecture list
+    region_map: Rect,                  // inside of the region map text
+    overview_image: Rect,              // the choropleth image itself
+    overview_grid: Vec<(Rect, usize)>, // fallback grid squares and their prefecture
+    sidebar: Rect,                     // inside of the overview's prefecture list
+    stats_regions: Rect,               // inside of the stats view's regional breakdown
+    stats_lines: usize,                // regional breakdown height once wrapped
+}
+
+// Labels for the visit form fields, in tab order
+const VISIT_FORM_LABELS: [&str; 4] = [
+    "Date (YYYY-MM-DD or YYYY-MM-DD..YYYY-MM-DD)",
+    "Level (1-5)",
+    "Notes",
+    "Companions (comma separated)",
+];
+
+struct VisitForm {
+    prefecture: String,
+    editing: Option<usize>, // index into user_progress.visits, None for a new visit
+    fields: [String; 4],
+    focus: usize,
+    error: Option<String>,
+}
+
+impl VisitForm {
+    fn new(prefecture: &str, level: u8) -> Self {
+        Self {
+            prefecture: prefecture.to_string(),
+            editing: None,
+            fields: [
+                chrono::Local::now().date_naive().to_string(),
+                level.max(1).to_string(),
+                String::new(),
+                String::new(),
+            ],
+            focus: 0,
+            error: None,
+        }
+    }
+
+    fn edit(index: usize, visit: &Visit) -> Self {
+        let dates = match (visit.start_date, visit.end_date) {
+            (Some(start), Some(end)) => format!("{}..{}", start, end),
+            (Some(start), None) => start.to_string(),
+            _ => String::new(),
+        };
+
         Self {
-            prefecture_levels: HashMap::new(),
+            prefecture: visit.prefecture.clone(),
+            editing: Some(index),
+            fields: [
+                dates,
+                visit.level.to_string(),
+                visit.notes.clone(),
+                visit.companions.join(", "),
+            ],
+            focus: 0,
+            error: None,
         }
     }
+
+    fn to_visit(&self) -> Result<Visit, String> {
+        let (start_date, end_date) = parse_date_range(&self.fields[0])?;
+        let level = match self.fields[1].trim().parse::<u8>() {
+            Ok(level @ 1..=5) => level,
+            _ => return Err("Level must be a number from 1 to 5".to_string()),
+        };
+        let companions = self.fields[3]
+            .split(',')
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect();
+
+        Ok(Visit {
+            prefecture: self.prefecture.clone(),
+            start_date,
+            end_date,
+            level,
+            notes: self.fields[2].trim().to_string(),
+            companions,
+        })
+    }
+}
+
+struct ExportPrompt {
+    format: ExportFormat,
+    path: String,
+    confirm_overwrite: bool, // the path exists and we're waiting for y/n
+}
+
+/// Editing the tags of one prefecture, as comma separated text.
+struct TagPrompt {
+    prefecture: String, // ISO code
+    text: String,
+}
+
+// Rows of the list view's filter and sort menu, top to bottom
+const LIST_MENU_LABELS: [&str; 6] = ["Lowest level", "Highest level", "Region", "Tag", "Sort by", "Order"];
+
+/// The profile switcher: every known profile, and the name being typed
+/// when creating a new one.
+struct ProfileMenu {
+    profiles: Vec<String>,
+    selected: usize,
+    new_name: Option<String>,
+}
+
+/// Incremental `/` search. The list and map views show only the matches
+/// until it's closed.
+struct Search {
+    query: String,
+    matches: Vec<usize>, // prefecture indices, best match first
+    previous: usize,     // selection to go back to on Esc
+}
+
+/// The `:` command palette: every action and profile by name, narrowed
+/// down by a fuzzy query.
+struct CommandPalette {
+    query: String,
+    entries: Vec<PaletteEntry>,
+    matches: Vec<usize>, // entry indices, best match first
+    selected: usize,     // row in `matches`
+}
+
+struct PaletteEntry {
+    label: String,
+    target: PaletteTarget,
+}
+
+enum PaletteTarget {
+    Action(Action),
+    Profile(String),
+}
+
+/// One-line message shown at the bottom of the screen until the next key.
+struct StatusMessage {
+    text: String,
+    is_error: bool,
 }
 
 struct JTermApp {
+    regions: Vec<Region>,
     prefectures: Vec<Prefecture>,
+    profile: String, // whose progress is loaded; see storage::get_profile_dir
     user_progress: UserProgress,
     selected_index: usize,
+    view: View,
     show_help: bool,
-    show_map: bool,
-    show_stats: bool,
     show_detail: bool,
-    show_alt_map: bool,
     list_state: ratatui::widgets::ListState,
     map_scroll: u16,
-    map_selected_index: usize,
     stats_scroll: u16,
     prefecture_scroll: u16,
+    journal_index: usize,
+    visit_form: Option<VisitForm>,
+    export_prompt: Option<ExportPrompt>,
+    search: Option<Search>,
+    tag_prompt: Option<TagPrompt>,
+    list_menu: Option<usize>, // focused row while the filter and sort menu is open
+    settings: Settings,
+    theme: Theme,
+    ascii: bool, // see glyphs::GlyphMode
+    keymap: Keymap,
+    history: History,
+    profile_menu: Option<ProfileMenu>,
+    command_palette: Option<CommandPalette>,
+    last_export_dir: Option<PathBuf>,
+    status: Option<StatusMessage>,
     image_picker: Option<Picker>,
     japan_map_image: Option<Box<dyn StatefulProtocol>>,
+    japan_map_levels: Vec<u8>, // levels the current map image was drawn with
+    japan_map_size: (u32, u32), // pixel size of the rasterized map
+    areas: ScreenAreas,
 }
 
 impl JTermApp {
-    fn new() -> io::Result<Self> {
-        let prefectures = get_prefectures();
-        let user_progress = load_user_progress()?;
-        
+    fn new(profile: &str) -> io::Result<Self> {
+        let geo_data = load_geo_data()?;
+        let user_progress = load_user_progress(profile)?;
+        let settings = load_settings()?;
+        let (theme, theme_error) = startup_theme(&settings);
+        let (keymap, keymap_error) = match load_keymap() {
+            Ok(keymap) => (keymap, None),
+            Err(err) => (Keymap::default(), Some(format!("Could not load key bindings: {}", err))),
+        };
+
+        let mut app = Self::with_data(profile, geo_data, user_progress, settings);
+        app.theme = theme;
+        app.keymap = keymap;
+        app.status = theme_error.or(keymap_error).map(|text| StatusMessage { text, is_error: true });
+        Ok(app)
+    }
+
+    /// An app over data that's already loaded, with the default theme and
+    /// keys. Nothing is read from disk.
+    fn with_data(profile: &str, geo_data: GeoData, user_progress: UserProgress, settings: Settings) -> Self {
+        let GeoData { regions, prefectures } = geo_data;
+        let theme = builtin_themes().swap_remove(0).with_palette(settings.palette);
+        let ascii = settings.glyphs.is_ascii();
+
         let mut list_state = ListState::default();
         list_state.select(Some(0));
         
-        Ok(Self {
+        Self {
+            regions,
             prefectures,
+            profile: profile.to_string(),
             user_progress,
             selected_index: 0,
+            view: View::List,
             show_help: false,
-            show_map: false,
-            show_stats: false,
             show_detail: false,
-            show_alt_map: false,
             list_state,
             map_scroll: 0,
-            map_selected_index: 0,
             stats_scroll: 0,
             prefecture_scroll: 0,
+            journal_index: 0,
+            visit_form: None,
+            export_prompt: None,
+            search: None,
+            tag_prompt: None,
+            list_menu: None,
+            settings,
+            theme,
+            ascii,
+            keymap: Keymap::default(),
+            history: History::default(),
+            profile_menu: None,
+            command_palette: None,
+            last_export_dir: None,
+            status: None,
             image_picker: None,
             japan_map_image: None,
-        })
+            japan_map_levels: Vec::new(),
+            japan_map_size: (0, 0),
+            areas: ScreenAreas::default(),
+        }
     }
 
     fn init_japan_map(&mut self) -> io::Result<()> {
         // Initialize image picker with better font size detection
-        let mut picker = Picker::from_termios().unwrap_or_else(|_| {
+        let picker = Picker::from_termios().unwrap_or_else(|_| {
             eprintln!("Failed to query terminal, using default picker with Ghostty-friendly font size");
-            Picker::new((14, 28).into()) // Better default for Ghostty 17pt font
+            Picker::new((14, 28)) // Better default for Ghostty 17pt font
         });
         
         // Debug output to verify what we detected
         eprintln!("Protocol type: {:?}", picker.protocol_type);
         eprintln!("Font size: {:?}", picker.font_size);
         
-        // Try to load the transparent PNG file
-        let img_path = "img/japanex_jterm.png";
-        
-        // Check if file exists first
-        if !std::path::Path::new(img_path).exists() {
-            return Ok(());
-        }
-        
-        // Load PNG directly using image crate
-        match image::open(img_path) {
-            Ok(dynamic_img) => {
-                // Create ratatui-image protocol with resize
-                let image = picker.new_resize_protocol(dynamic_img);
-                
-                self.image_picker = Some(picker);
-                self.japan_map_image = Some(image);
-            }
-            Err(_) => return Ok(()), // Skip if image loading fails
-        }
+        self.image_picker = Some(picker);
+        self.refresh_map_image();
         
         Ok(())
     }
 
-    fn get_level_color(level: u8) -> Color {
-        match level {
-            0 => FlexokiTheme::TX,  // No change - use default text color
-            1 => FlexokiTheme::RE,  // Red
-            2 => FlexokiTheme::YE,  // Yellow
-            3 => FlexokiTheme::GR,  // Green
-            4 => FlexokiTheme::PU,  // Purple
-            5 => FlexokiTheme::BL,  // Blue
-            _ => FlexokiTheme::TX,
+    /// Redraw the choropleth image if any level changed since it was last
+    /// rasterized. Cheap to call on every frame.
+    fn refresh_map_image(&mut self) {
+        let levels: Vec<u8> = self
+            .prefectures
+            .iter()
+            .map(|p| self.get_prefecture_level(&p.iso_code))
+            .collect();
+        if self.japan_map_image.is_some() && levels == self.japan_map_levels {
+            return;
+        }
+        if self.image_picker.is_none() {
+            return;
+        }
+
+        let fills: Vec<(&str, map::Fill)> = self
+            .prefectures
+            .iter()
+            .zip(&levels)
+            .map(|(p, &level)| (p.iso_code.as_str(), self.map_fill(level)))
+            .collect();
+        let svg = map::choropleth_svg(&fills, self.theme.text);
+
+        // Skip if rasterizing fails; the overview falls back to the grid
+        if let Ok(image) = map::rasterize(&svg, MAP_IMAGE_WIDTH)
+            && let Some(picker) = self.image_picker.as_mut()
+        {
+            self.japan_map_size = (image.width(), image.height());
+            self.japan_map_image = Some(picker.new_resize_protocol(image));
+            self.japan_map_levels = levels;
         }
     }
 
@@ -179,30 +411,426 @@ impl JTermApp {
         }
     }
 
+    fn selected_prefecture(&self) -> Option<&Prefecture> {
+        self.prefectures.get(self.selected_index)
+    }
+
+    fn prefecture_by_key(&self, key: &str) -> Option<&Prefecture> {
+        self.prefectures.iter().find(|p| p.iso_code == key)
+    }
+
+    /// "Tokyo (東京都)" for a storage key, or the key itself if the dataset
+    /// doesn't know it.
+    fn prefecture_label(&self, key: &str) -> String {
+        match self.prefecture_by_key(key) {
+            Some(prefecture) => format!("{} ({})", prefecture.name_en, prefecture.name_jp),
+            None => key.to_string(),
+        }
+    }
+
     fn set_prefecture_level(&mut self, level: u8) {
-        let index = if self.show_map {
-            self.map_selected_index
-        } else {
-            self.selected_index
-        };
-        
-        if let Some(prefecture) = self.prefectures.get(index) {
-            self.user_progress.prefecture_levels.insert(prefecture.name_en.clone(), level);
+        let position = self.selected_position();
+        if let Some(prefecture) = self.selected_prefecture() {
+            let key = prefecture.iso_code.clone();
+            let before = self.user_progress.clone();
+            let previous = self.get_prefecture_level(&key);
+            let today = chrono::Local::now().date_naive();
+            match self.user_progress.log_level(&key, level, today) {
+                Ok(()) => {
+                    let label = self.prefecture_label(&key);
+                    let description = if level == previous {
+                        format!("Logged a level {} visit to {}", level, label)
+                    } else {
+                        format!("Set {} to level {} (was {})", label, level, previous)
+                    };
+                    self.record_change(description, before);
+                }
+                Err(earlier) => self.set_error(format!(
+                    "{} stays at level {}: an earlier visit was level {}. Edit or delete it in the journal ({})",
+                    self.prefecture_label(&key),
+                    previous,
+                    earlier,
+                    self.keymap.hint(&[Action::JournalView])
+                )),
+            }
+        }
+        // A filtered list may no longer show it; move on to its neighbour
+        self.reselect_near(position);
+    }
+
+    fn get_prefecture_level(&self, key: &str) -> u8 {
+        self.user_progress.level(key)
+    }
+
+    fn open_visit_form(&mut self) {
+        if let Some(prefecture) = self.selected_prefecture() {
+            let level = self.get_prefecture_level(&prefecture.iso_code);
+            self.visit_form = Some(VisitForm::new(&prefecture.iso_code, level));
+        }
+    }
+
+    fn edit_selected_visit(&mut self) {
+        let journal = self.user_progress.journal();
+        if let Some((index, visit)) = journal.get(self.journal_index) {
+            self.visit_form = Some(VisitForm::edit(*index, visit));
+        }
+    }
+
+    fn delete_selected_visit(&mut self) {
+        let journal = self.user_progress.journal();
+        if let Some((index, _)) = journal.get(self.journal_index) {
+            let index = *index;
+            let before = self.user_progress.clone();
+            let visit = self.user_progress.visits.remove(index);
+            self.journal_index = self.journal_index.min(self.user_progress.visits.len().saturating_sub(1));
+            let description = format!("Deleted a visit to {}", self.prefecture_label(&visit.prefecture));
+            self.record_change(description, before);
+        }
+    }
+
+    /// Put a change to the journal on the undo stack and say what it was.
+    /// `before` is the journal as it was; nothing is recorded if it didn't
+    /// actually change.
+    fn record_change(&mut self, description: String, before: UserProgress) {
+        if before != self.user_progress {
+            self.set_status(format!("{}{}", description, self.key_tip(Action::Undo, "undo")));
+            self.history.record(description, before);
+        }
+    }
+
+    fn undo(&mut self) -> io::Result<()> {
+        match self.history.undo(&mut self.user_progress) {
+            Some(description) => self.after_history_step(format!("Undid: {}", description)),
+            None => {
+                self.set_status("Nothing to undo".to_string());
+                Ok(())
+            }
+        }
+    }
+
+    fn redo(&mut self) -> io::Result<()> {
+        match self.history.redo(&mut self.user_progress) {
+            Some(description) => self.after_history_step(format!("Redid: {}", description)),
+            None => {
+                self.set_status("Nothing to redo".to_string());
+                Ok(())
+            }
         }
     }
 
-    fn get_prefecture_level(&self, prefecture_name: &str) -> u8 {
-        self.user_progress.prefecture_levels.get(prefecture_name).copied().unwrap_or(0)
+    fn after_history_step(&mut self, status: String) -> io::Result<()> {
+        let position = self.selected_position();
+        self.journal_index = self.journal_index.min(self.user_progress.visits.len().saturating_sub(1));
+        self.reselect_near(position);
+        self.set_status(status);
+        self.save_progress()
+    }
+
+    fn handle_visit_form_key(&mut self, code: KeyCode) -> io::Result<()> {
+        let Some(form) = self.visit_form.as_mut() else {
+            return Ok(());
+        };
+
+        match code {
+            KeyCode::Esc => self.visit_form = None,
+            KeyCode::Tab | KeyCode::Down => form.focus = (form.focus + 1) % form.fields.len(),
+            KeyCode::BackTab | KeyCode::Up => {
+                form.focus = (form.focus + form.fields.len() - 1) % form.fields.len()
+            }
+            KeyCode::Backspace => {
+                form.fields[form.focus].pop();
+            }
+            KeyCode::Char(c) => form.fields[form.focus].push(c),
+            KeyCode::Enter => match form.to_visit() {
+                Ok(visit) => {
+                    let editing = form.editing.filter(|&index| index < self.user_progress.visits.len());
+                    self.visit_form = None;
+                    let before = self.user_progress.clone();
+                    let label = self.prefecture_label(&visit.prefecture);
+                    let description = match editing {
+                        Some(index) => {
+                            self.user_progress.visits[index] = visit;
+                            format!("Edited a visit to {}", label)
+                        }
+                        None => {
+                            self.user_progress.visits.push(visit);
+                            format!("Logged a visit to {}", label)
+                        }
+                    };
+                    self.record_change(description, before);
+                    self.save_progress()?;
+                }
+                Err(err) => form.error = Some(err),
+            },
+            _ => {}
+        }
+
+        Ok(())
     }
 
     fn save_progress(&self) -> io::Result<()> {
-        save_user_progress(&self.user_progress)
+        save_user_progress(&self.profile, &self.user_progress)
+    }
+
+    fn open_profile_menu(&mut self) {
+        let profiles = match self.profile_names() {
+            Ok(profiles) => profiles,
+            Err(err) => {
+                self.set_error(format!("Could not list profiles: {}", err));
+                return;
+            }
+        };
+
+        let selected = profiles.iter().position(|p| *p == self.profile).unwrap_or(0);
+        self.profile_menu = Some(ProfileMenu { profiles, selected, new_name: None });
+    }
+
+    // Saved profiles plus the active and default ones, which may not have
+    // saved anything yet, sorted
+    fn profile_names(&self) -> io::Result<Vec<String>> {
+        let default = self.settings.default_profile.as_deref().unwrap_or(storage::DEFAULT_PROFILE);
+        let mut profiles = list_profiles()?;
+        for name in [self.profile.as_str(), default] {
+            if !profiles.iter().any(|p| p == name) {
+                profiles.push(name.to_string());
+            }
+        }
+        profiles.sort();
+        Ok(profiles)
+    }
+
+    fn handle_profile_menu_key(&mut self, key: KeyEvent) {
+        let code = key.code;
+        let Some(menu) = self.profile_menu.as_mut() else {
+            return;
+        };
+
+        if let Some(name) = menu.new_name.as_mut() {
+            match code {
+                KeyCode::Esc => menu.new_name = None,
+                KeyCode::Backspace => {
+                    name.pop();
+                }
+                KeyCode::Char(c) => name.push(c),
+                KeyCode::Enter => {
+                    let name = name.trim().to_string();
+                    match validate_profile_name(&name) {
+                        Ok(()) => {
+                            self.profile_menu = None;
+                            self.switch_profile(&name);
+                        }
+                        Err(err) => self.set_error(err.to_string()),
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        // Whatever key opened the menu closes it again
+        if code == KeyCode::Esc || self.keymap.action(key) == Some(Action::Profiles) {
+            self.profile_menu = None;
+            return;
+        }
+
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => menu.selected = menu.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => menu.selected = (menu.selected + 1).min(menu.profiles.len() - 1),
+            KeyCode::Char('n') => menu.new_name = Some(String::new()),
+            KeyCode::Char('d') => {
+                let name = menu.profiles[menu.selected].clone();
+                self.settings.default_profile = Some(name.clone());
+                match save_settings(&self.settings) {
+                    Ok(()) => self.set_status(format!("'{}' is now the default profile", name)),
+                    Err(err) => self.set_error(format!("Could not save settings: {}", err)),
+                }
+            }
+            KeyCode::Enter => {
+                let name = menu.profiles[menu.selected].clone();
+                self.profile_menu = None;
+                self.switch_profile(&name);
+            }
+            _ => {}
+        }
+    }
+
+    /// Load another profile's progress in place of the current one. Undo
+    /// history belongs to the old profile and is dropped.
+    fn switch_profile(&mut self, profile: &str) {
+        if profile == self.profile {
+            return;
+        }
+        match load_user_progress(profile) {
+            Ok(progress) => {
+                self.profile = profile.to_string();
+                self.user_progress = progress;
+                self.history = History::default();
+                self.journal_index = 0;
+                self.reselect_near(None);
+                self.set_status(format!(
+                    "Switched to profile '{}' ({} visits)",
+                    profile,
+                    self.user_progress.visits.len()
+                ));
+            }
+            Err(err) => self.set_error(format!("Could not load profile '{}': {}", profile, err)),
+        }
+    }
+
+    /// Open the command palette with every action that makes sense from a
+    /// list, then the other profiles. Moving and closing are left out.
+    fn open_command_palette(&mut self) {
+        let mut entries: Vec<PaletteEntry> = Action::ALL
+            .into_iter()
+            .filter(|action| {
+                !matches!(
+                    action,
+                    Action::Palette | Action::Up | Action::Down | Action::Left | Action::Right | Action::Close
+                )
+            })
+            .map(|action| {
+                let label = match action {
+                    Action::SetLevel(level) => format!("Set level {}: {}", level, JTermApp::get_level_text(level)),
+                    _ => action.label(),
+                };
+                PaletteEntry { label, target: PaletteTarget::Action(action) }
+            })
+            .collect();
+        // A profile that can't be listed is still reachable from the menu
+        let profiles = self.profile_names().unwrap_or_default();
+        entries.extend(profiles.into_iter().filter(|name| *name != self.profile).map(|name| PaletteEntry {
+            label: format!("Switch to profile '{}'", name),
+            target: PaletteTarget::Profile(name),
+        }));
+
+        let matches = (0..entries.len()).collect();
+        self.command_palette = Some(CommandPalette { query: String::new(), entries, matches, selected: 0 });
+    }
+
+    /// Keys for the open command palette. Returns the action picked, for
+    /// the caller to perform; profiles are switched to here.
+    fn handle_command_palette_key(&mut self, code: KeyCode) -> Option<Action> {
+        let palette = self.command_palette.as_mut()?;
+
+        match code {
+            KeyCode::Esc => self.command_palette = None,
+            KeyCode::Up => palette.selected = palette.selected.saturating_sub(1),
+            KeyCode::Down => palette.selected = (palette.selected + 1).min(palette.matches.len().saturating_sub(1)),
+            KeyCode::Backspace => {
+                palette.query.pop();
+                update_palette_matches(palette);
+            }
+            KeyCode::Char(c) => {
+                palette.query.push(c);
+                update_palette_matches(palette);
+            }
+            KeyCode::Enter => {
+                let index = palette.matches.get(palette.selected).copied()?;
+                let entry = self.command_palette.take()?.entries.swap_remove(index);
+                match entry.target {
+                    PaletteTarget::Action(action) => return Some(action),
+                    PaletteTarget::Profile(name) => self.switch_profile(&name),
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Switch to the next theme, built-in ones first, and remember it.
+    /// Theme files are reread each time, so edits show up on the next
+    /// switch.
+    fn cycle_theme(&mut self) {
+        let (themes, errors) = load_themes();
+        let next = themes
+            .iter()
+            .position(|theme| theme.name == self.theme.name)
+            .map_or(0, |i| (i + 1) % themes.len());
+        self.set_theme(themes[next].clone());
+        self.settings.theme = Some(self.theme.name.clone());
+
+        if let Err(err) = save_settings(&self.settings) {
+            self.set_error(format!("Could not save settings: {}", err));
+        } else if let Some(error) = errors.first() {
+            self.set_error(format!("Theme: {} · skipped {}", self.theme.name, error));
+        } else {
+            self.set_status(format!(
+                "Theme: {} ({} of {}){}",
+                self.theme.name,
+                next + 1,
+                themes.len(),
+                self.key_tip(Action::NextTheme, "next")
+            ));
+        }
+    }
+
+    /// Switch to the next colorblind-safe level palette, or back to the
+    /// theme's own colors.
+    fn cycle_palette(&mut self) {
+        self.settings.palette = cycle(&Palette::ALL, &self.settings.palette, 1);
+        // Reload the theme to get its own level colors back
+        let (themes, _) = load_themes();
+        let theme = themes
+            .into_iter()
+            .find(|theme| theme.name == self.theme.name)
+            .unwrap_or_else(|| self.theme.clone());
+        self.set_theme(theme);
+        let tip = self.key_tip(Action::NextPalette, "next");
+        self.save_appearance(format!("Level colors: {}{}", self.settings.palette.label(), tip));
+    }
+
+    fn toggle_patterns(&mut self) {
+        self.settings.patterns = !self.settings.patterns;
+        // The overview image has the old fills baked in
+        self.japan_map_image = None;
+        let state = if self.settings.patterns { "on" } else { "off" };
+        self.save_appearance(format!("Level glyphs and map patterns {}", state));
+    }
+
+    fn toggle_ascii(&mut self) {
+        self.ascii = !self.ascii;
+        self.settings.glyphs = if self.ascii { GlyphMode::Ascii } else { GlyphMode::Unicode };
+        let state = if self.ascii { "on" } else { "off" };
+        self.save_appearance(format!("ASCII mode {}", state));
+    }
+
+    // " · u: undo" for a status message, or nothing if `action` has no key
+    fn key_tip(&self, action: Action, what: &str) -> String {
+        if self.keymap.is_bound(&[action]) {
+            format!(" · {}: {}", self.keymap.hint(&[action]), what)
+        } else {
+            String::new()
+        }
+    }
+
+    /// `text` as it should be drawn: without emoji in ASCII mode.
+    fn plain(&self, text: &str) -> String {
+        if self.ascii { strip_emoji(text) } else { text.to_string() }
+    }
+
+    fn level_glyph(&self, level: u8) -> &'static str {
+        let glyphs = if self.ascii { ASCII_LEVEL_GLYPHS } else { LEVEL_GLYPHS };
+        glyphs.get(level as usize).unwrap_or(&"?")
+    }
+
+    // Use `theme` with the chosen palette's level colors
+    fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme.with_palette(self.settings.palette);
+        // The overview image has the old colors baked in
+        self.japan_map_image = None;
+    }
+
+    fn save_appearance(&mut self, status: String) {
+        match save_settings(&self.settings) {
+            Ok(()) => self.set_status(status),
+            Err(err) => self.set_error(format!("Could not save settings: {}", err)),
+        }
     }
 
-    fn export_to_json(&self) -> io::Result<()> {
+    /// Summary numbers shared by `jterm stats --json` and the JSON export.
+    fn stats_json(&self) -> serde_json::Value {
         let stats = self.calculate_stats();
-        let export_data = serde_json::json!({
-            "export_date": chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        serde_json::json!({
             "total_prefectures": stats.total_prefectures,
             "visited_count": stats.total_prefectures - stats.level_counts[0],
             "total_score": stats.total_score,
@@ -216,324 +844,666 @@ impl JTermApp {
                 "lived": stats.level_counts[5]
             },
             "regional_progress": stats.region_stats,
-            "prefecture_details": self.prefectures.iter().map(|p| {
-                serde_json::json!({
-                    "name_en": p.name_en,
-                    "name_jp": p.name_jp,
-                    "region": p.region,
-                    "level": self.get_prefecture_level(&p.name_en),
-                    "capital": p.capital,
-                    "population": p.population,
-                    "area_km2": p.area_km2
-                })
-            }).collect::<Vec<_>>()
-        });
+        })
+    }
+
+    fn export(&self, format: ExportFormat, export_path: &Path) -> io::Result<()> {
+        match format {
+            ExportFormat::Json => self.export_to_json(export_path),
+            ExportFormat::Csv => self.export_to_csv(export_path),
+            ExportFormat::Svg => fs::write(export_path, self.progress_map_svg()),
+            ExportFormat::Keikenchi => fs::write(export_path, self.keikenchi_share_text()),
+            ExportFormat::Png => {
+                let image = map::rasterize(&self.progress_map_svg(), EXPORT_IMAGE_WIDTH)
+                    .map_err(|e| io::Error::other(format!("could not render map: {}", e)))?;
+                image
+                    .save_with_format(export_path, image::ImageFormat::Png)
+                    .map_err(io::Error::other)
+            }
+        }
+    }
+
+    fn keikenchi_share_text(&self) -> String {
+        keikenchi::share_text(&self.prefectures, |p| self.get_prefecture_level(&p.iso_code))
+    }
+
+    /// Colored prefecture map with a level legend and the total score, as
+    /// written by the SVG and PNG exports.
+    fn progress_map_svg(&self) -> String {
+        let stats = self.calculate_stats();
+        let visited = stats.total_prefectures - stats.level_counts[0];
+
+        let fills: Vec<(&str, map::Fill)> = self
+            .prefectures
+            .iter()
+            .map(|p| (p.iso_code.as_str(), self.map_fill(self.get_prefecture_level(&p.iso_code))))
+            .collect();
+        let legend: Vec<(map::Fill, String)> = (0..=5)
+            .rev()
+            .map(|level| {
+                let label = format!(
+                    "{} {} ({})",
+                    level,
+                    Self::get_level_text(level),
+                    stats.level_counts[level as usize]
+                );
+                (self.map_fill(level), label)
+            })
+            .collect();
+        let summary = format!(
+            "Score {} / {} · Visited {} / {}",
+            stats.total_score,
+            stats.total_prefectures * 5,
+            visited,
+            stats.total_prefectures
+        );
 
-        let home_dir = dirs::home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found"))?;
-        let export_path = home_dir.join("jterm_export.json");
-        fs::write(&export_path, serde_json::to_string_pretty(&export_data)?)?;
+        map::progress_svg(&fills, self.theme.background, self.theme.text, "Japan Travel Progress", &summary, &legend)
+    }
+
+    fn export_to_json(&self, export_path: &Path) -> io::Result<()> {
+        let mut export_data = self.stats_json();
+        export_data["export_date"] = serde_json::json!(chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string());
+        export_data["prefecture_details"] = self.prefectures.iter().map(|p| {
+            serde_json::json!({
+                "code": p.code,
+                "iso_code": p.iso_code,
+                "name_en": p.name_en,
+                "name_jp": p.name_jp,
+                "region": p.region,
+                "level": self.get_prefecture_level(&p.iso_code),
+                "capital": p.capital,
+                "population": p.population,
+                "area_km2": p.area_km2,
+                "visits": self.user_progress.visits_for(&p.iso_code).collect::<Vec<_>>()
+            })
+        }).collect();
+
+        fs::write(export_path, serde_json::to_string_pretty(&export_data)?)?;
         Ok(())
     }
 
-    fn export_to_csv(&self) -> io::Result<()> {
+    fn export_to_csv(&self, export_path: &Path) -> io::Result<()> {
         let mut csv_content = String::new();
-        csv_content.push_str("Prefecture_EN,Prefecture_JP,Region,Level,Experience,Capital,Population,Area_km2\n");
+        csv::write_record(&mut csv_content, &CSV_COLUMNS);
         
         for prefecture in &self.prefectures {
-            let level = self.get_prefecture_level(&prefecture.name_en);
+            let level = self.get_prefecture_level(&prefecture.iso_code);
             let experience = Self::get_level_text(level);
-            csv_content.push_str(&format!(
-                "{},{},{},{},{},{},{},{}\n",
-                prefecture.name_en,
-                prefecture.name_jp,
-                prefecture.region,
-                level,
-                experience,
-                prefecture.capital,
-                prefecture.population,
-                prefecture.area_km2
-            ));
+            let last_visit = self
+                .user_progress
+                .last_visit(&prefecture.iso_code)
+                .map(|date| date.to_string())
+                .unwrap_or_default();
+            let notes: Vec<&str> = self
+                .user_progress
+                .visits_for(&prefecture.iso_code)
+                .map(|visit| visit.notes.as_str())
+                .filter(|notes| !notes.is_empty())
+                .collect();
+
+            csv::write_record(&mut csv_content, &[
+                prefecture.iso_code.clone(),
+                prefecture.name_en.clone(),
+                prefecture.name_jp.clone(),
+                prefecture.region.clone(),
+                level.to_string(),
+                experience.to_string(),
+                prefecture.capital.clone(),
+                prefecture.population.to_string(),
+                prefecture.area_km2.to_string(),
+                last_visit,
+                notes.join("; "),
+            ]);
         }
 
-        let home_dir = dirs::home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found"))?;
-        let export_path = home_dir.join("jterm_export.csv");
-        fs::write(&export_path, csv_content)?;
+        fs::write(export_path, csv_content)?;
         Ok(())
     }
 
-    fn render_map(&self) -> Vec<String> {
-        let mut map_lines = Vec::new();
-        let mut prefecture_index = 0;
-        
-        // Hokkaido
-        map_lines.push("╭─────────────── HOKKAIDO REGION ─────────────────╮".to_string());
-        let hokkaido_level = self.get_prefecture_level("Hokkaido");
-        let hokkaido_color = match hokkaido_level {
-            0 => "⬜", 1 => "🟥", 2 => "🟨", 
-            3 => "🟩", 4 => "🟪", 5 => "🟦", _ => "⬜"
+    fn set_status(&mut self, text: String) {
+        self.status = Some(StatusMessage { text, is_error: false });
+    }
+
+    fn set_error(&mut self, text: String) {
+        self.status = Some(StatusMessage { text, is_error: true });
+    }
+
+    fn open_export_prompt(&mut self, format: ExportFormat) {
+        match default_export_path(self.last_export_dir.as_deref(), format) {
+            Ok(path) => {
+                self.export_prompt = Some(ExportPrompt {
+                    format,
+                    path: path.display().to_string(),
+                    confirm_overwrite: false,
+                });
+            }
+            Err(err) => self.set_error(format!("Export failed: {}", err)),
+        }
+    }
+
+    fn handle_export_prompt_key(&mut self, code: KeyCode) {
+        let Some(prompt) = self.export_prompt.as_mut() else {
+            return;
         };
-        let hokkaido_indicator = if prefecture_index == self.map_selected_index { "►" } else { " " };
-        map_lines.push(format!(" {} {} Hokkaido (北海道) - Level {} ", hokkaido_indicator, hokkaido_color, hokkaido_level));
-        prefecture_index += 1;
-        map_lines.push("╰─────────────────────────────────────────────────╯".to_string());
-        map_lines.push("".to_string());
-
-        // Tohoku Region
-        map_lines.push("╭─────────────── TOHOKU REGION ───────────────────╮".to_string());
-        let tohoku_prefectures = [
-            ("Aomori", "青森"), ("Iwate", "岩手"), ("Akita", "秋田"),
-            ("Miyagi", "宮城"), ("Yamagata", "山形"), ("Fukushima", "福島")
-        ];
-        
-        for (name_en, name_jp) in &tohoku_prefectures {
-            let level = self.get_prefecture_level(name_en);
-            let color = match level {
-                0 => "⬜", 1 => "🟥", 2 => "🟨", 
-                3 => "🟩", 4 => "🟪", 5 => "🟦", _ => "⬜"
-            };
-            let indicator = if prefecture_index == self.map_selected_index { "►" } else { " " };
-            map_lines.push(format!(" {} {} {:<8} ({}) - Level {} ", indicator, color, name_en, name_jp, level));
-            prefecture_index += 1;
-        }
-        map_lines.push("╰─────────────────────────────────────────────────╯".to_string());
-        map_lines.push("".to_string());
-
-        // Kanto Region
-        map_lines.push("╭─────────────── KANTO REGION ────────────────────╮".to_string());
-        let kanto_prefectures = [
-            ("Ibaraki", "茨城"), ("Tochigi", "栃木"), ("Gunma", "群馬"),
-            ("Saitama", "埼玉"), ("Tokyo", "東京"), ("Chiba", "千葉"), ("Kanagawa", "神奈川")
-        ];
-        
-        for (name_en, name_jp) in &kanto_prefectures {
-            let level = self.get_prefecture_level(name_en);
-            let color = match level {
-                0 => "⬜", 1 => "🟥", 2 => "🟨", 
-                3 => "🟩", 4 => "🟪", 5 => "🟦", _ => "⬜"
-            };
-            let indicator = if prefecture_index == self.map_selected_index { "►" } else { " " };
-            map_lines.push(format!(" {} {} {:<8} ({}) - Level {} ", indicator, color, name_en, name_jp, level));
-            prefecture_index += 1;
-        }
-        map_lines.push("╰─────────────────────────────────────────────────╯".to_string());
-        map_lines.push("".to_string());
-
-        // Chubu Region
-        map_lines.push("╭─────────────── CHUBU REGION ────────────────────╮".to_string());
-        let chubu_prefectures = [
-            ("Niigata", "新潟"), ("Toyama", "富山"), ("Ishikawa", "石川"),
-            ("Fukui", "福井"), ("Yamanashi", "山梨"), ("Nagano", "長野"),
-            ("Gifu", "岐阜"), ("Shizuoka", "静岡"), ("Aichi", "愛知")
-        ];
-        
-        for (name_en, name_jp) in &chubu_prefectures {
-            let level = self.get_prefecture_level(name_en);
-            let color = match level {
-                0 => "⬜", 1 => "🟥", 2 => "🟨", 
-                3 => "🟩", 4 => "🟪", 5 => "🟦", _ => "⬜"
-            };
-            let indicator = if prefecture_index == self.map_selected_index { "►" } else { " " };
-            map_lines.push(format!(" {} {} {:<8} ({}) - Level {} ", indicator, color, name_en, name_jp, level));
-            prefecture_index += 1;
-        }
-        map_lines.push("╰─────────────────────────────────────────────────╯".to_string());
-        map_lines.push("".to_string());
-
-        // Kansai Region
-        map_lines.push("╭─────────────── KANSAI REGION ───────────────────╮".to_string());
-        let kansai_prefectures = [
-            ("Mie", "三重"), ("Shiga", "滋賀"), ("Kyoto", "京都"),
-            ("Osaka", "大阪"), ("Hyogo", "兵庫"), ("Nara", "奈良"), ("Wakayama", "和歌山")
-        ];
-        
-        for (name_en, name_jp) in &kansai_prefectures {
-            let level = self.get_prefecture_level(name_en);
-            let color = match level {
-                0 => "⬜", 1 => "🟥", 2 => "🟨", 
-                3 => "🟩", 4 => "🟪", 5 => "🟦", _ => "⬜"
-            };
-            let indicator = if prefecture_index == self.map_selected_index { "►" } else { " " };
-            map_lines.push(format!(" {} {} {:<8} ({}) - Level {} ", indicator, color, name_en, name_jp, level));
-            prefecture_index += 1;
-        }
-        map_lines.push("╰─────────────────────────────────────────────────╯".to_string());
-        map_lines.push("".to_string());
-
-        // Chugoku Region
-        map_lines.push("╭─────────────── CHUGOKU REGION ──────────────────╮".to_string());
-        let chugoku_prefectures = [
-            ("Tottori", "鳥取"), ("Shimane", "島根"), ("Okayama", "岡山"),
-            ("Hiroshima", "広島"), ("Yamaguchi", "山口")
-        ];
-        
-        for (name_en, name_jp) in &chugoku_prefectures {
-            let level = self.get_prefecture_level(name_en);
-            let color = match level {
-                0 => "⬜", 1 => "🟥", 2 => "🟨", 
-                3 => "🟩", 4 => "🟪", 5 => "🟦", _ => "⬜"
-            };
-            let indicator = if prefecture_index == self.map_selected_index { "►" } else { " " };
-            map_lines.push(format!(" {} {} {:<8} ({}) - Level {} ", indicator, color, name_en, name_jp, level));
-            prefecture_index += 1;
+
+        if prompt.confirm_overwrite {
+            match code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => self.finish_export(),
+                KeyCode::Esc => self.export_prompt = None,
+                _ => prompt.confirm_overwrite = false, // back to editing the path
+            }
+            return;
         }
-        map_lines.push("╰─────────────────────────────────────────────────╯".to_string());
-        map_lines.push("".to_string());
 
-        // Shikoku Region
-        map_lines.push("╭─────────────── SHIKOKU REGION ──────────────────╮".to_string());
-        let shikoku_prefectures = [
-            ("Tokushima", "徳島"), ("Kagawa", "香川"), ("Ehime", "愛媛"), ("Kochi", "高知")
-        ];
-        
-        for (name_en, name_jp) in &shikoku_prefectures {
-            let level = self.get_prefecture_level(name_en);
-            let color = match level {
-                0 => "⬜", 1 => "🟥", 2 => "🟨", 
-                3 => "🟩", 4 => "🟪", 5 => "🟦", _ => "⬜"
-            };
-            let indicator = if prefecture_index == self.map_selected_index { "►" } else { " " };
-            map_lines.push(format!(" {} {} {:<8} ({}) - Level {} ", indicator, color, name_en, name_jp, level));
-            prefecture_index += 1;
-        }
-        map_lines.push("╰─────────────────────────────────────────────────╯".to_string());
-        map_lines.push("".to_string());
-
-        // Kyushu Region
-        map_lines.push("╭─────────────── KYUSHU REGION ───────────────────╮".to_string());
-        let kyushu_prefectures = [
-            ("Fukuoka", "福岡"), ("Saga", "佐賀"), ("Nagasaki", "長崎"),
-            ("Kumamoto", "熊本"), ("Oita", "大分"), ("Miyazaki", "宮崎"), ("Kagoshima", "鹿児島")
-        ];
-        
-        for (name_en, name_jp) in &kyushu_prefectures {
-            let level = self.get_prefecture_level(name_en);
-            let color = match level {
-                0 => "⬜", 1 => "🟥", 2 => "🟨", 
-                3 => "🟩", 4 => "🟪", 5 => "🟦", _ => "⬜"
-            };
-            let indicator = if prefecture_index == self.map_selected_index { "►" } else { " " };
-            map_lines.push(format!(" {} {} {:<8} ({}) - Level {} ", indicator, color, name_en, name_jp, level));
-            prefecture_index += 1;
-        }
-        map_lines.push("╰─────────────────────────────────────────────────╯".to_string());
-        map_lines.push("".to_string());
-
-        // Okinawa
-        map_lines.push("╭─────────────── OKINAWA REGION ──────────────────╮".to_string());
-        let okinawa_level = self.get_prefecture_level("Okinawa");
-        let okinawa_color = match okinawa_level {
-            0 => "⬜", 1 => "🟥", 2 => "🟨", 
-            3 => "🟩", 4 => "🟪", 5 => "🟦", _ => "⬜"
+        match code {
+            KeyCode::Esc => self.export_prompt = None,
+            KeyCode::Backspace => {
+                prompt.path.pop();
+            }
+            KeyCode::Char(c) => prompt.path.push(c),
+            KeyCode::Enter => match expand_home(&prompt.path) {
+                Ok(path) if path.as_os_str().is_empty() => {}
+                Ok(path) if path.is_dir() => {
+                    self.set_error(format!("{} is a directory", path.display()));
+                }
+                Ok(path) if path.exists() => prompt.confirm_overwrite = true,
+                Ok(_) => self.finish_export(),
+                Err(err) => self.set_error(format!("Export failed: {}", err)),
+            },
+            _ => {}
+        }
+    }
+
+    fn finish_export(&mut self) {
+        let Some(prompt) = self.export_prompt.take() else {
+            return;
         };
-        let okinawa_indicator = if prefecture_index == self.map_selected_index { "►" } else { " " };
-        map_lines.push(format!(" {} {} Okinawa (沖縄) - Level {} ", okinawa_indicator, okinawa_color, okinawa_level));
-        map_lines.push("╰─────────────────────────────────────────────────╯".to_string());
 
-        map_lines
+        let result = expand_home(&prompt.path).and_then(|path| {
+            self.export(prompt.format, &path)?;
+            Ok(path)
+        });
+
+        match result {
+            Ok(path) => {
+                self.last_export_dir = path.parent().map(Path::to_path_buf);
+                self.set_status(format!("Exported {} to {}", prompt.format.label(), path.display()));
+            }
+            Err(err) => self.set_error(format!("Export to {} failed: {}", prompt.path.trim(), err)),
+        }
     }
 
-    fn get_prefecture_line(&self, target_index: usize) -> usize {
-        let mut line_number = 0;
-        let mut prefecture_index = 0;
-        
-        // Hokkaido
-        line_number += 1; // header
-        if prefecture_index == target_index { return line_number; }
-        line_number += 1; // prefecture line
-        prefecture_index += 1;
-        line_number += 2; // border + empty line
-        
-        // Tohoku
-        line_number += 1; // header
-        for _ in 0..6 { // 6 prefectures
-            if prefecture_index == target_index { return line_number; }
-            line_number += 1;
-            prefecture_index += 1;
-        }
-        line_number += 2; // border + empty line
-        
-        // Kanto
-        line_number += 1; // header
-        for _ in 0..7 { // 7 prefectures
-            if prefecture_index == target_index { return line_number; }
-            line_number += 1;
-            prefecture_index += 1;
-        }
-        line_number += 2; // border + empty line
-        
-        // Chubu
-        line_number += 1; // header
-        for _ in 0..9 { // 9 prefectures
-            if prefecture_index == target_index { return line_number; }
-            line_number += 1;
-            prefecture_index += 1;
-        }
-        line_number += 2; // border + empty line
-        
-        // Kansai
-        line_number += 1; // header
-        for _ in 0..7 { // 7 prefectures
-            if prefecture_index == target_index { return line_number; }
-            line_number += 1;
-            prefecture_index += 1;
-        }
-        line_number += 2; // border + empty line
-        
-        // Chugoku
-        line_number += 1; // header
-        for _ in 0..5 { // 5 prefectures
-            if prefecture_index == target_index { return line_number; }
-            line_number += 1;
-            prefecture_index += 1;
-        }
-        line_number += 2; // border + empty line
-        
-        // Shikoku
-        line_number += 1; // header
-        for _ in 0..4 { // 4 prefectures
-            if prefecture_index == target_index { return line_number; }
-            line_number += 1;
-            prefecture_index += 1;
-        }
-        line_number += 2; // border + empty line
-        
-        // Kyushu
-        line_number += 1; // header
-        for _ in 0..7 { // 7 prefectures
-            if prefecture_index == target_index { return line_number; }
-            line_number += 1;
-            prefecture_index += 1;
-        }
-        line_number += 2; // border + empty line
-        
-        // Okinawa
-        line_number += 1; // header
-        if prefecture_index == target_index { return line_number; }
-        line_number += 1;
-        
-        line_number
+    /// Two-cell block in a level's color, for the region map, the overview
+    /// grid and the level legends. With patterns on it is the level's glyph
+    /// instead, so it reads without color.
+    fn level_swatch(&self, level: u8) -> ratatui::text::Span<'static> {
+        let color = if level == 0 { self.theme.muted } else { self.theme.level(level) };
+        let text = match (self.settings.patterns, level) {
+            (true, _) => format!("{} ", self.level_glyph(level)),
+            (false, 0) => "░░".to_string(),
+            (false, _) => "██".to_string(),
+        };
+        ratatui::text::Span::styled(text, Style::default().fg(color))
     }
-    
-    fn ensure_selected_visible(&mut self) {
-        let selected_line = self.get_prefecture_line(self.map_selected_index);
-        let terminal_height = 25; // Approximate visible lines in map view
-        let scroll_top = self.map_scroll as usize;
-        let scroll_bottom = scroll_top + terminal_height;
-        
-        // If selected line is above visible area, scroll up
-        if selected_line < scroll_top {
-            self.map_scroll = selected_line as u16;
-        }
-        // If selected line is below visible area, scroll down
-        else if selected_line >= scroll_bottom {
-            self.map_scroll = (selected_line + 1).saturating_sub(terminal_height) as u16;
+
+    /// How a prefecture at `level` is painted on the map images.
+    fn map_fill(&self, level: u8) -> map::Fill {
+        map::Fill {
+            color: self.theme.map_fill(level),
+            pattern: if self.settings.patterns { level_pattern(level) } else { None },
         }
     }
 
+    /// Lay out the region map: one bordered block per region, one row per
+    /// prefecture. Row positions are recorded while the lines are built so
+    /// selection and scrolling can never disagree with what is drawn.
+    fn render_map(&self) -> RegionMap {
+        const INNER_WIDTH: usize = 49;
+        // Columns are measured in terminal cells, so kanji count double
+        let name_width = self.prefectures.iter().map(|p| p.name_en.width()).max().unwrap_or(0);
+        let jp_width = self.prefectures.iter().map(|p| p.short_name_jp().width()).max().unwrap_or(0);
+
+        let mut map = RegionMap { lines: Vec::new(), rows: Vec::new() };
+        let visible = self.visible_prefectures();
+
+        for region in &self.regions {
+            let members: Vec<usize> = (0..self.prefectures.len())
+                .filter(|&i| self.prefectures[i].region == region.name && visible.contains(&i))
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+
+            if !map.lines.is_empty() {
+                map.lines.push(ratatui::text::Line::default());
+            }
+
+            let title = format!(" {} REGION ", region.name.to_uppercase());
+            let right = INNER_WIDTH.saturating_sub(15 + title.width());
+            map.lines.push(format!("╭{}{}{}╮", "─".repeat(15), title, "─".repeat(right)).into());
+
+            for index in members {
+                let prefecture = &self.prefectures[index];
+                let level = self.get_prefecture_level(&prefecture.iso_code);
+                let indicator = if index == self.selected_index { "►" } else { " " };
+
+                map.rows.push((map.lines.len(), index));
+                map.lines.push(ratatui::text::Line::from(vec![
+                    ratatui::text::Span::raw(format!(" {} ", indicator)),
+                    self.level_swatch(level),
+                    ratatui::text::Span::raw(format!(
+                        " {} {} - Level {} ",
+                        pad_to_width(&prefecture.name_en, name_width),
+                        pad_to_width(&format!("({})", prefecture.short_name_jp()), jp_width + 2),
+                        level
+                    )),
+                ]));
+            }
+
+            map.lines.push(format!("╰{}╯", "─".repeat(INNER_WIDTH)).into());
+        }
+
+        map
+    }
+
+    /// Move the map selection `step` rows up or down in display order.
+    fn move_map_selection(&mut self, step: isize) {
+        let map = self.render_map();
+        let Some(position) = map.rows.iter().position(|(_, index)| *index == self.selected_index) else {
+            return;
+        };
+
+        let target = position.saturating_add_signed(step).min(map.rows.len() - 1);
+        self.select_prefecture(map.rows[target].1);
+        self.ensure_selected_visible();
+    }
+    
+    fn ensure_selected_visible(&mut self) {
+        let Some(selected_line) = self.render_map().line_of(self.selected_index) else {
+            return;
+        };
+        let visible_height = usize::from(self.areas.region_map.height.max(1));
+        let scroll_top = self.map_scroll as usize;
+        let scroll_bottom = scroll_top + visible_height;
+        
+        // If selected line is above visible area, scroll up
+        if selected_line < scroll_top {
+            self.map_scroll = selected_line as u16;
+        }
+        // If selected line is below visible area, scroll down
+        else if selected_line >= scroll_bottom {
+            self.map_scroll = (selected_line + 1).saturating_sub(visible_height) as u16;
+        }
+    }
+
+    // Furthest each view can scroll before its last line leaves the bottom
+    // of the area the last frame gave it
+    fn max_map_scroll(&self) -> u16 {
+        max_scroll(self.render_map().lines.len(), self.areas.region_map)
+    }
+
+    fn max_stats_scroll(&self) -> u16 {
+        max_scroll(self.areas.stats_lines, self.areas.stats_regions)
+    }
+
+    fn max_prefecture_scroll(&self) -> u16 {
+        max_scroll(self.visible_prefectures().len(), self.areas.sidebar)
+    }
+
+    /// Up arrow or wheel up: scroll or move the selection in the current view.
+    fn move_up(&mut self) {
+        match self.view {
+            View::RegionMap => self.map_scroll = self.map_scroll.saturating_sub(1),
+            View::Stats => self.stats_scroll = self.stats_scroll.saturating_sub(1),
+            View::Journal => self.journal_index = self.journal_index.saturating_sub(1),
+            View::List | View::Overview => self.step_selection(-1),
+        }
+    }
+
+    /// Down arrow or wheel down: scroll or move the selection in the current view.
+    fn move_down(&mut self) {
+        match self.view {
+            View::RegionMap => {
+                if self.map_scroll < self.max_map_scroll() {
+                    self.map_scroll += 1;
+                }
+            }
+            View::Stats => {
+                if self.stats_scroll < self.max_stats_scroll() {
+                    self.stats_scroll += 1;
+                }
+            }
+            View::Journal => {
+                if self.journal_index + 1 < self.user_progress.visits.len() {
+                    self.journal_index += 1;
+                }
+            }
+            View::List | View::Overview => self.step_selection(1),
+        }
+    }
+
+    /// The one place the selection changes, so every view highlights and
+    /// edits the same prefecture.
+    fn select_prefecture(&mut self, index: usize) {
+        self.selected_index = index;
+    }
+
+    /// Move the selection `step` places through the prefectures the current
+    /// view lists.
+    fn step_selection(&mut self, step: isize) {
+        if self.view == View::RegionMap {
+            self.move_map_selection(step);
+            return;
+        }
+        let visible = self.visible_prefectures();
+        let Some(position) = visible.iter().position(|&index| index == self.selected_index) else {
+            // The selection was filtered out; start from the first match
+            if let Some(&first) = visible.first() {
+                self.select_prefecture(first);
+            }
+            return;
+        };
+        let target = position.saturating_add_signed(step).min(visible.len() - 1);
+        self.select_prefecture(visible[target]);
+    }
+
+    /// Prefectures the list and map views show: the search matches while a
+    /// search is open, otherwise all of them in dataset order. The list view
+    /// also applies its filters and, outside a search, its sort order.
+    fn visible_prefectures(&self) -> Vec<usize> {
+        let shown = match &self.search {
+            Some(search) => search.matches.clone(),
+            None => (0..self.prefectures.len()).collect(),
+        };
+        if self.view != View::List {
+            return shown;
+        }
+
+        let options = &self.settings.list;
+        let mut shown = options.filter(&self.prefectures, &self.user_progress, shown);
+        if self.search.is_none() {
+            options.sort(&self.prefectures, &self.user_progress, &mut shown);
+        }
+        shown
+    }
+
+    fn selected_position(&self) -> Option<usize> {
+        self.visible_prefectures().iter().position(|&index| index == self.selected_index)
+    }
+
+    // If the selection is no longer shown, select whatever now sits at its
+    // old position (or the first prefecture shown)
+    fn reselect_near(&mut self, position: Option<usize>) {
+        let visible = self.visible_prefectures();
+        if visible.contains(&self.selected_index) {
+            return;
+        }
+        if let Some(&index) = visible.get(position.unwrap_or(0).min(visible.len().saturating_sub(1))) {
+            self.select_prefecture(index);
+        }
+    }
+
+    fn open_list_menu(&mut self) {
+        if self.view == View::List {
+            self.list_menu = Some(0);
+        }
+    }
+
+    fn handle_list_menu_key(&mut self, key: KeyEvent) {
+        let Some(row) = self.list_menu else {
+            return;
+        };
+
+        // Whatever key opened the menu closes it again
+        if matches!(key.code, KeyCode::Esc | KeyCode::Enter) || self.keymap.action(key) == Some(Action::Filter) {
+            self.list_menu = None;
+            if let Err(err) = save_settings(&self.settings) {
+                self.set_error(format!("Could not save settings: {}", err));
+            }
+            return;
+        }
+
+        let position = self.selected_position();
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.list_menu = Some(row.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('j') => self.list_menu = Some((row + 1).min(LIST_MENU_LABELS.len() - 1)),
+            KeyCode::Left | KeyCode::Char('h') => self.adjust_list_option(row, -1),
+            KeyCode::Right | KeyCode::Char('l') => self.adjust_list_option(row, 1),
+            KeyCode::Char('r') => self.settings.list = Default::default(),
+            _ => {}
+        }
+        self.reselect_near(position);
+    }
+
+    // Step the value on menu row `row` to its previous or next choice
+    fn adjust_list_option(&mut self, row: usize, step: isize) {
+        let options = &mut self.settings.list;
+        match row {
+            0 => options.min_level = options.min_level.saturating_add_signed(step as i8).min(options.max_level),
+            1 => options.max_level = options.max_level.saturating_add_signed(step as i8).clamp(options.min_level, 5),
+            2 => {
+                let mut choices = vec![None];
+                choices.extend(self.regions.iter().map(|r| Some(r.name.clone())));
+                options.region = cycle(&choices, &options.region, step);
+            }
+            3 => {
+                let mut choices = vec![None];
+                choices.extend(self.user_progress.all_tags().into_iter().map(|t| Some(t.to_string())));
+                options.tag = cycle(&choices, &options.tag, step);
+            }
+            4 => options.sort = cycle(&SortKey::ALL, &options.sort, step),
+            _ => options.reverse = !options.reverse,
+        }
+    }
+
+    fn open_tag_prompt(&mut self) {
+        if let Some(prefecture) = self.selected_prefecture() {
+            let text = self.user_progress.tags_for(&prefecture.iso_code).join(", ");
+            self.tag_prompt = Some(TagPrompt { prefecture: prefecture.iso_code.clone(), text });
+        }
+    }
+
+    fn handle_tag_prompt_key(&mut self, code: KeyCode) -> io::Result<()> {
+        let Some(prompt) = self.tag_prompt.as_mut() else {
+            return Ok(());
+        };
+
+        match code {
+            KeyCode::Esc => self.tag_prompt = None,
+            KeyCode::Backspace => {
+                prompt.text.pop();
+            }
+            KeyCode::Char(c) => prompt.text.push(c),
+            KeyCode::Enter => {
+                let tags = prompt.text.split(',').map(str::to_string).collect();
+                let prefecture = prompt.prefecture.clone();
+                self.tag_prompt = None;
+                let position = self.selected_position();
+                let before = self.user_progress.clone();
+                self.user_progress.set_tags(&prefecture, tags);
+                let description = format!("Changed the tags of {}", self.prefecture_label(&prefecture));
+                self.record_change(description, before);
+                self.save_progress()?;
+                self.reselect_near(position);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn open_search(&mut self) {
+        if matches!(self.view, View::List | View::RegionMap | View::Overview) {
+            self.search = Some(Search {
+                query: String::new(),
+                matches: (0..self.prefectures.len()).collect(),
+                previous: self.selected_index,
+            });
+        }
+    }
+
+    fn handle_search_key(&mut self, code: KeyCode) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc => {
+                let previous = search.previous;
+                self.search = None;
+                self.select_prefecture(previous);
+            }
+            KeyCode::Enter => self.search = None,
+            KeyCode::Up => self.step_selection(-1),
+            KeyCode::Down => self.step_selection(1),
+            KeyCode::Backspace => {
+                search.query.pop();
+                self.update_search();
+            }
+            KeyCode::Char(c) => {
+                search.query.push(c);
+                self.update_search();
+            }
+            _ => {}
+        }
+        if self.view == View::RegionMap {
+            self.ensure_selected_visible();
+        }
+    }
+
+    // Re-run the search after the query changed and select the best match
+    fn update_search(&mut self) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        search.matches = search::search(&self.prefectures, &self.regions, &search.query);
+        if let Some(&best) = self.visible_prefectures().first() {
+            self.select_prefecture(best);
+        }
+    }
+
+    /// Switch to `view`, or back to the list if it's already showing.
+    fn toggle_view(&mut self, view: View) {
+        self.view = if self.view == view { View::List } else { view };
+        match self.view {
+            View::RegionMap => self.ensure_selected_visible(),
+            View::List => self.reselect_near(None),
+            _ => {}
+        }
+    }
+
+    /// Do what a key bound to `action` does in the current view. Quitting
+    /// is left to the caller.
+    fn perform(&mut self, action: Action) -> io::Result<()> {
+        match action {
+            Action::Quit => {}
+            Action::Help => self.show_help = !self.show_help,
+            Action::Palette => self.open_command_palette(),
+            Action::Search => self.open_search(),
+            Action::Filter => self.open_list_menu(),
+            Action::Tags => self.open_tag_prompt(),
+            Action::Profiles => self.open_profile_menu(),
+            Action::NextTheme => self.cycle_theme(),
+            Action::NextPalette => self.cycle_palette(),
+            Action::TogglePatterns => self.toggle_patterns(),
+            Action::ToggleAscii => self.toggle_ascii(),
+            Action::Undo => self.undo()?,
+            Action::Redo => self.redo()?,
+            Action::MapView => self.toggle_view(View::RegionMap),
+            Action::StatsView => self.toggle_view(View::Stats),
+            Action::OverviewView => self.toggle_view(View::Overview),
+            Action::JournalView => self.toggle_view(View::Journal),
+            Action::Up => self.move_up(),
+            Action::Down => self.move_down(),
+            Action::Left if self.view == View::RegionMap => self.move_map_selection(-1),
+            Action::Right if self.view == View::RegionMap => self.move_map_selection(1),
+            Action::Left | Action::Right => {}
+            Action::Open => {
+                if self.view == View::Journal {
+                    self.edit_selected_visit();
+                } else {
+                    self.show_detail = !self.show_detail;
+                }
+            }
+            Action::Close => self.show_detail = false,
+            Action::LogVisit => self.open_visit_form(),
+            Action::DeleteVisit => {
+                if self.view == View::Journal {
+                    self.delete_selected_visit();
+                    self.save_progress()?;
+                }
+            }
+            Action::SetLevel(level) => {
+                self.set_prefecture_level(level);
+                self.save_progress()?;
+            }
+            Action::Export(format) => self.open_export_prompt(format),
+        }
+        Ok(())
+    }
+
+    /// Clicks select the prefecture under the pointer; the wheel scrolls like
+    /// the arrow keys. Positions are checked against the areas the last
+    /// frame recorded.
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let position = ratatui::layout::Position::new(mouse.column, mouse.row);
+        match mouse.kind {
+            MouseEventKind::ScrollUp => self.move_up(),
+            MouseEventKind::ScrollDown => self.move_down(),
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.status = None;
+                let index = match self.view {
+                    View::RegionMap if self.areas.region_map.contains(position) => {
+                        let line = (mouse.row - self.areas.region_map.y + self.map_scroll) as usize;
+                        let map = self.render_map();
+                        map.rows.iter().find(|(l, _)| *l == line).map(|&(_, index)| index)
+                    }
+                    View::Overview => self.overview_prefecture_at(position),
+                    View::List if self.areas.list.contains(position) => {
+                        let row = self.list_state.offset() + (mouse.row - self.areas.list.y) as usize;
+                        self.visible_prefectures().get(row).copied()
+                    }
+                    _ => None,
+                };
+                if let Some(index) = index {
+                    self.select_prefecture(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Prefecture under a click in the overview: a shape on the choropleth,
+    /// a square of the fallback grid or a row of the sidebar.
+    fn overview_prefecture_at(&self, position: ratatui::layout::Position) -> Option<usize> {
+        let image = self.areas.overview_image;
+        let iso_code = if image.contains(position) {
+            // Sample the middle of the clicked cell
+            let x = (f32::from(position.x - image.x) + 0.5) / f32::from(image.width);
+            let y = (f32::from(position.y - image.y) + 0.5) / f32::from(image.height);
+            map::prefecture_at(x, y)
+        } else {
+            None
+        };
+        if let Some(iso_code) = iso_code {
+            return self.prefectures.iter().position(|p| p.iso_code == iso_code);
+        }
+
+        if let Some((_, index)) = self.areas.overview_grid.iter().find(|(cell, _)| cell.contains(position)) {
+            return Some(*index);
+        }
+
+        let sidebar = self.areas.sidebar;
+        if sidebar.contains(position) {
+            let row = (position.y - sidebar.y + self.prefecture_scroll) as usize;
+            return self.visible_prefectures().get(row).copied();
+        }
+        None
+    }
+
     fn calculate_stats(&self) -> TravelStats {
         let mut level_counts = [0; 6]; // counts for levels 0-5
         let mut region_stats = HashMap::new();
-        let mut total_score = 0;
 
         // Initialize region stats
         for prefecture in &self.prefectures {
@@ -542,9 +1512,8 @@ impl JTermApp {
 
         // Calculate statistics
         for prefecture in &self.prefectures {
-            let level = self.get_prefecture_level(&prefecture.name_en);
+            let level = self.get_prefecture_level(&prefecture.iso_code);
             level_counts[level as usize] += 1;
-            total_score += level as u32;
 
             let (visited, total) = region_stats.get_mut(&prefecture.region).unwrap();
             *total += 1;
@@ -555,129 +1524,42 @@ impl JTermApp {
 
         TravelStats {
             total_prefectures: self.prefectures.len(),
-            total_score,
+            total_score: keikenchi::total(self.prefectures.iter().map(|p| self.get_prefecture_level(&p.iso_code))),
             level_counts,
             region_stats,
         }
     }
 }
 
-fn get_prefectures() -> Vec<Prefecture> {
-    vec![
-        // Hokkaido
-        Prefecture { 
-            name_en: "Hokkaido".to_string(), 
-            name_jp: "北海道".to_string(), 
-            region: "Hokkaido".to_string(), 
-            map_pos: (2, 30), 
-            map_char: "北".to_string(),
-            capital: "Sapporo".to_string(),
-            population: 5250000,
-            area_km2: 83424,
-        },
-        
-        // Tohoku
-        Prefecture { name_en: "Aomori".to_string(), name_jp: "青森県".to_string(), region: "Tohoku".to_string(), map_pos: (8, 32), map_char: "青".to_string(), capital: "Aomori".to_string(), population: 1240000, area_km2: 9646 },
-        Prefecture { name_en: "Iwate".to_string(), name_jp: "岩手県".to_string(), region: "Tohoku".to_string(), map_pos: (10, 34), map_char: "岩".to_string(), capital: "Morioka".to_string(), population: 1200000, area_km2: 15275 },
-        Prefecture { name_en: "Miyagi".to_string(), name_jp: "宮城県".to_string(), region: "Tohoku".to_string(), map_pos: (12, 32), map_char: "宮".to_string(), capital: "Sendai".to_string(), population: 2300000, area_km2: 7282 },
-        Prefecture { name_en: "Akita".to_string(), name_jp: "秋田県".to_string(), region: "Tohoku".to_string(), map_pos: (10, 30), map_char: "秋".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Yamagata".to_string(), name_jp: "山形県".to_string(), region: "Tohoku".to_string(), map_pos: (12, 30), map_char: "形".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Fukushima".to_string(), name_jp: "福島県".to_string(), region: "Tohoku".to_string(), map_pos: (14, 32), map_char: "福".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        
-        // Kanto
-        Prefecture { name_en: "Ibaraki".to_string(), name_jp: "茨城県".to_string(), region: "Kanto".to_string(), map_pos: (16, 34), map_char: "茨".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Tochigi".to_string(), name_jp: "栃木県".to_string(), region: "Kanto".to_string(), map_pos: (16, 32), map_char: "栃".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Gunma".to_string(), name_jp: "群馬県".to_string(), region: "Kanto".to_string(), map_pos: (16, 30), map_char: "群".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Saitama".to_string(), name_jp: "埼玉県".to_string(), region: "Kanto".to_string(), map_pos: (18, 30), map_char: "埼".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Chiba".to_string(), name_jp: "千葉県".to_string(), region: "Kanto".to_string(), map_pos: (18, 34), map_char: "千".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Tokyo".to_string(), name_jp: "東京都".to_string(), region: "Kanto".to_string(), map_pos: (18, 32), map_char: "東".to_string(), capital: "Tokyo".to_string(), population: 14094034, area_km2: 2194 },
-        Prefecture { name_en: "Kanagawa".to_string(), name_jp: "神奈川県".to_string(), region: "Kanto".to_string(), map_pos: (20, 32), map_char: "神".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        
-        // Chubu
-        Prefecture { name_en: "Niigata".to_string(), name_jp: "新潟県".to_string(), region: "Chubu".to_string(), map_pos: (14, 28), map_char: "新".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Toyama".to_string(), name_jp: "富山県".to_string(), region: "Chubu".to_string(), map_pos: (18, 26), map_char: "富".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Ishikawa".to_string(), name_jp: "石川県".to_string(), region: "Chubu".to_string(), map_pos: (18, 24), map_char: "石".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Fukui".to_string(), name_jp: "福井県".to_string(), region: "Chubu".to_string(), map_pos: (20, 24), map_char: "井".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Yamanashi".to_string(), name_jp: "山梨県".to_string(), region: "Chubu".to_string(), map_pos: (20, 30), map_char: "梨".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Nagano".to_string(), name_jp: "長野県".to_string(), region: "Chubu".to_string(), map_pos: (18, 28), map_char: "長".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Gifu".to_string(), name_jp: "岐阜県".to_string(), region: "Chubu".to_string(), map_pos: (20, 26), map_char: "岐".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Shizuoka".to_string(), name_jp: "静岡県".to_string(), region: "Chubu".to_string(), map_pos: (22, 30), map_char: "静".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Aichi".to_string(), name_jp: "愛知県".to_string(), region: "Chubu".to_string(), map_pos: (22, 28), map_char: "愛".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        
-        // Kansai
-        Prefecture { name_en: "Mie".to_string(), name_jp: "三重県".to_string(), region: "Kansai".to_string(), map_pos: (24, 28), map_char: "三".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Shiga".to_string(), name_jp: "滋賀県".to_string(), region: "Kansai".to_string(), map_pos: (22, 26), map_char: "滋".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Kyoto".to_string(), name_jp: "京都府".to_string(), region: "Kansai".to_string(), map_pos: (22, 24), map_char: "京".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Osaka".to_string(), name_jp: "大阪府".to_string(), region: "Kansai".to_string(), map_pos: (24, 24), map_char: "大".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Hyogo".to_string(), name_jp: "兵庫県".to_string(), region: "Kansai".to_string(), map_pos: (24, 22), map_char: "兵".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Nara".to_string(), name_jp: "奈良県".to_string(), region: "Kansai".to_string(), map_pos: (24, 26), map_char: "奈".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Wakayama".to_string(), name_jp: "和歌山県".to_string(), region: "Kansai".to_string(), map_pos: (26, 24), map_char: "和".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        
-        // Chugoku
-        Prefecture { name_en: "Tottori".to_string(), name_jp: "鳥取県".to_string(), region: "Chugoku".to_string(), map_pos: (24, 20), map_char: "鳥".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Shimane".to_string(), name_jp: "島根県".to_string(), region: "Chugoku".to_string(), map_pos: (26, 18), map_char: "島".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Okayama".to_string(), name_jp: "岡山県".to_string(), region: "Chugoku".to_string(), map_pos: (26, 20), map_char: "岡".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Hiroshima".to_string(), name_jp: "広島県".to_string(), region: "Chugoku".to_string(), map_pos: (26, 22), map_char: "広".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Yamaguchi".to_string(), name_jp: "山口県".to_string(), region: "Chugoku".to_string(), map_pos: (28, 18), map_char: "口".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        
-        // Shikoku
-        Prefecture { name_en: "Tokushima".to_string(), name_jp: "徳島県".to_string(), region: "Shikoku".to_string(), map_pos: (28, 24), map_char: "徳".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Kagawa".to_string(), name_jp: "香川県".to_string(), region: "Shikoku".to_string(), map_pos: (28, 22), map_char: "香".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Ehime".to_string(), name_jp: "愛媛県".to_string(), region: "Shikoku".to_string(), map_pos: (28, 20), map_char: "媛".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Kochi".to_string(), name_jp: "高知県".to_string(), region: "Shikoku".to_string(), map_pos: (30, 22), map_char: "高".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        
-        // Kyushu
-        Prefecture { name_en: "Fukuoka".to_string(), name_jp: "福岡県".to_string(), region: "Kyushu".to_string(), map_pos: (30, 16), map_char: "岡".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Saga".to_string(), name_jp: "佐賀県".to_string(), region: "Kyushu".to_string(), map_pos: (32, 16), map_char: "佐".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Nagasaki".to_string(), name_jp: "長崎県".to_string(), region: "Kyushu".to_string(), map_pos: (32, 14), map_char: "崎".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Kumamoto".to_string(), name_jp: "熊本県".to_string(), region: "Kyushu".to_string(), map_pos: (32, 18), map_char: "熊".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Oita".to_string(), name_jp: "大分県".to_string(), region: "Kyushu".to_string(), map_pos: (30, 18), map_char: "分".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Miyazaki".to_string(), name_jp: "宮崎県".to_string(), region: "Kyushu".to_string(), map_pos: (34, 18), map_char: "崎".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        Prefecture { name_en: "Kagoshima".to_string(), name_jp: "鹿児島県".to_string(), region: "Kyushu".to_string(), map_pos: (34, 16), map_char: "鹿".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-        
-        // Okinawa
-        Prefecture { name_en: "Okinawa".to_string(), name_jp: "沖縄県".to_string(), region: "Okinawa".to_string(), map_pos: (40, 12), map_char: "沖".to_string(), capital: "TBD".to_string(), population: 1000000, area_km2: 5000 },
-    ]
-}
-
-fn get_data_dir() -> io::Result<PathBuf> {
-    let mut path = dirs::home_dir().ok_or_else(|| {
-        io::Error::new(io::ErrorKind::NotFound, "Could not find home directory")
-    })?;
-    path.push(".jterm");
-    
-    if !path.exists() {
-        fs::create_dir_all(&path)?;
-    }
-    
-    Ok(path)
-}
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let invocation = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(invocation) => invocation,
+        Err(err) => {
+            eprintln!("jterm: {}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let profile = match active_profile(invocation.profile) {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("jterm: {}", err);
+            std::process::exit(2);
+        }
+    };
 
-fn load_user_progress() -> io::Result<UserProgress> {
-    let data_dir = get_data_dir()?;
-    let progress_file = data_dir.join("progress.json");
-    
-    if progress_file.exists() {
-        let contents = fs::read_to_string(progress_file)?;
-        serde_json::from_str(&contents)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-    } else {
-        Ok(UserProgress::default())
+    if !matches!(invocation.command, Command::Tui) {
+        if let Err(err) = cli::run(invocation.command, &profile) {
+            // The reader went away, e.g. `jterm stats | head`; not an error
+            if err.downcast_ref::<io::Error>().is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe) {
+                return Ok(());
+            }
+            eprintln!("jterm: {}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
     }
-}
-
-fn save_user_progress(progress: &UserProgress) -> io::Result<()> {
-    let data_dir = get_data_dir()?;
-    let progress_file = data_dir.join("progress.json");
-    
-    let contents = serde_json::to_string_pretty(progress)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    
-    fs::write(progress_file, contents)
-}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut app = JTermApp::new()?;
+    let mut app = JTermApp::new(&profile)?;
     let _ = app.init_japan_map(); // Initialize Japan map image BEFORE raw mode
     
     enable_raw_mode()?;
@@ -702,6 +1584,96 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// The theme named in the settings, or the default one, and what went
+/// wrong finding it, to show on the status line.
+fn startup_theme(settings: &Settings) -> (Theme, Option<String>) {
+    let (mut themes, errors) = load_themes();
+    let mut error = errors.first().map(|error| format!("Skipped theme {}", error));
+    let mut index = 0;
+    if let Some(name) = &settings.theme {
+        match themes.iter().position(|theme| &theme.name == name) {
+            Some(found) => index = found,
+            None => error = Some(format!("Unknown theme '{}', using {}", name, themes[0].name)),
+        }
+    }
+    (themes.swap_remove(index).with_palette(settings.palette), error)
+}
+
+/// Suggested export file: a timestamped name in `dir`, or the home directory.
+fn default_export_path(dir: Option<&Path>, format: ExportFormat) -> io::Result<PathBuf> {
+    let dir = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => dirs::home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found"))?,
+    };
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    Ok(dir.join(format!("jterm_export_{}.{}", stamp, format.extension())))
+}
+
+// Scroll offset that puts the last of `content_lines` on the bottom row of
+// `viewport`
+fn max_scroll(content_lines: usize, viewport: Rect) -> u16 {
+    content_lines.saturating_sub(usize::from(viewport.height)).min(usize::from(u16::MAX)) as u16
+}
+
+// `text` followed by enough spaces to fill `width` terminal cells
+fn pad_to_width(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())))
+}
+
+// Rank the palette's entries against its query, best first, and go back
+// to the top
+fn update_palette_matches(palette: &mut CommandPalette) {
+    let query = palette.query.trim().to_lowercase();
+    let mut scored: Vec<(u32, usize)> = palette
+        .entries
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| match query.is_empty() {
+            true => Some((0, index)),
+            false => search::score(&entry.label.to_lowercase(), &query).map(|score| (score, index)),
+        })
+        .collect();
+    // Stable, so equal scores keep the palette's order
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    palette.matches = scored.into_iter().map(|(_, index)| index).collect();
+    palette.selected = 0;
+}
+
+// The choice `step` places after `current`, wrapping around
+fn cycle<T: Clone + PartialEq>(choices: &[T], current: &T, step: isize) -> T {
+    let position = choices.iter().position(|c| c == current).unwrap_or(0);
+    let next = (position as isize + step).rem_euclid(choices.len() as isize) as usize;
+    choices[next].clone()
+}
+
+/// Cells ratatui-image fills when it fits an image of `image_size` pixels
+/// into `area` (Resize::Fit: shrink to fit keeping the aspect ratio, never
+/// enlarge, anchored top-left).
+fn fitted_image_area(image_size: (u32, u32), font_size: (u16, u16), area: Rect) -> Rect {
+    let (font_width, font_height) = (font_size.0.max(1) as f64, font_size.1.max(1) as f64);
+    let desired_width = (image_size.0 as f64 / font_width).ceil().max(1.0);
+    let desired_height = (image_size.1 as f64 / font_height).ceil().max(1.0);
+    let ratio = f64::min(
+        f64::from(area.width).min(desired_width) / desired_width,
+        f64::from(area.height).min(desired_height) / desired_height,
+    );
+    let width = (desired_width * ratio).round().max(1.0) as u16;
+    let height = (desired_height * ratio).round().max(1.0) as u16;
+    Rect::new(area.x, area.y, width.min(area.width), height.min(area.height))
+}
+
+/// Expand a leading `~` in a path typed by the user.
+fn expand_home(input: &str) -> io::Result<PathBuf> {
+    let input = input.trim();
+    match input.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home_dir = dirs::home_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found"))?;
+            Ok(home_dir.join(rest.trim_start_matches('/')))
+        }
+        _ => Ok(PathBuf::from(input)),
+    }
+}
+
 fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut JTermApp,
@@ -709,141 +1681,147 @@ fn run_app<B: Backend>(
     loop {
         terminal.draw(|f| ui(f, app))?;
 
-        if let Event::Key(key) = event::read()? {
-            match key.code {
-                KeyCode::Char('q') => return Ok(()),
-                KeyCode::Char('h') | KeyCode::F(1) => app.show_help = !app.show_help,
-                KeyCode::Char('m') => {
-                    app.show_map = !app.show_map;
-                    app.show_stats = false;
-                    app.show_alt_map = false;
-                },
-                KeyCode::Char('s') => {
-                    app.show_stats = !app.show_stats;
-                    app.show_map = false;
-                    app.show_alt_map = false;
-                },
-                KeyCode::Char('w') => {
-                    app.show_alt_map = !app.show_alt_map;
-                    app.show_map = false;
-                    app.show_stats = false;
-                },
-                KeyCode::Up | KeyCode::Char('k') => {
-                    if app.show_map {
-                        if app.map_scroll > 0 {
-                            app.map_scroll -= 1;
-                        }
-                    } else if app.show_stats {
-                        if app.stats_scroll > 0 {
-                            app.stats_scroll -= 1;
-                        }
-                    } else if app.show_alt_map {
-                        if app.prefecture_scroll > 0 {
-                            app.prefecture_scroll -= 1;
-                        }
-                    } else if app.selected_index > 0 {
-                        app.selected_index -= 1;
-                        app.list_state.select(Some(app.selected_index));
-                    }
-                }
-                KeyCode::Down | KeyCode::Char('j') => {
-                    if app.show_map {
-                        let map_lines = app.render_map();
-                        let max_scroll = map_lines.len().saturating_sub(25).max(0) as u16;
-                        if app.map_scroll < max_scroll {
-                            app.map_scroll += 1;
-                        }
-                    } else if app.show_stats {
-                        if app.stats_scroll < 20 { // Allow more scrolling to reach all regions
-                            app.stats_scroll += 1;
-                        }
-                    } else if app.show_alt_map {
-                        // Calculate max scroll for prefecture list (47 items - visible height)
-                        let visible_height = 20; // Approximate visible height in the sidebar
-                        let max_scroll = app.prefectures.len().saturating_sub(visible_height).max(0) as u16;
-                        if app.prefecture_scroll < max_scroll {
-                            app.prefecture_scroll += 1;
-                        }
-                    } else if app.selected_index < app.prefectures.len() - 1 {
-                        app.selected_index += 1;
-                        app.list_state.select(Some(app.selected_index));
-                    }
-                }
-                KeyCode::Left => {
-                    if app.show_map && app.map_selected_index > 0 {
-                        app.map_selected_index -= 1;
-                        app.ensure_selected_visible();
-                    }
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            Event::Mouse(mouse) => {
+                // Prompts are keyboard only
+                if app.visit_form.is_none()
+                    && app.export_prompt.is_none()
+                    && app.tag_prompt.is_none()
+                    && app.list_menu.is_none()
+                    && app.profile_menu.is_none()
+                    && app.command_palette.is_none()
+                {
+                    app.handle_mouse(mouse);
                 }
-                KeyCode::Right => {
-                    if app.show_map && app.map_selected_index < app.prefectures.len() - 1 {
-                        app.map_selected_index += 1;
-                        app.ensure_selected_visible();
-                    }
-                }
-                KeyCode::Enter => {
-                    app.show_detail = !app.show_detail;
-                }
-                KeyCode::Esc => {
-                    app.show_detail = false;
-                }
-                KeyCode::Char('0') => {
-                    app.set_prefecture_level(0);
-                    app.save_progress()?;
-                }
-                KeyCode::Char('1') => {
-                    app.set_prefecture_level(1);
-                    app.save_progress()?;
-                }
-                KeyCode::Char('2') => {
-                    app.set_prefecture_level(2);
-                    app.save_progress()?;
-                }
-                KeyCode::Char('3') => {
-                    app.set_prefecture_level(3);
-                    app.save_progress()?;
-                }
-                KeyCode::Char('4') => {
-                    app.set_prefecture_level(4);
-                    app.save_progress()?;
-                }
-                KeyCode::Char('5') => {
-                    app.set_prefecture_level(5);
-                    app.save_progress()?;
-                }
-                KeyCode::Char('e') => {
-                    match app.export_to_json() {
-                        Ok(_) => {}, // Success - could show a notification
-                        Err(_) => {}, // Error - could show error message
-                    }
-                }
-                KeyCode::Char('x') => {
-                    match app.export_to_csv() {
-                        Ok(_) => {}, // Success - could show a notification
-                        Err(_) => {}, // Error - could show error message
-                    }
+                continue;
+            }
+            Event::Resize(_, _) => {
+                // Lay out at the new size first, then bring the map
+                // selection back into view if the viewport shrank past it
+                terminal.draw(|f| ui(f, app))?;
+                if app.view == View::RegionMap {
+                    app.ensure_selected_visible();
                 }
-                _ => {}
+                continue;
             }
+            _ => continue,
+        };
+
+        // Any key dismisses the previous status message
+        app.status = None;
+
+        // Open prompts capture all keys
+        if app.visit_form.is_some() {
+            app.handle_visit_form_key(key.code)?;
+            continue;
+        }
+        if app.export_prompt.is_some() {
+            app.handle_export_prompt_key(key.code);
+            continue;
+        }
+        if app.tag_prompt.is_some() {
+            app.handle_tag_prompt_key(key.code)?;
+            continue;
+        }
+        if app.list_menu.is_some() {
+            app.handle_list_menu_key(key);
+            continue;
+        }
+        if app.profile_menu.is_some() {
+            app.handle_profile_menu_key(key);
+            continue;
+        }
+        if app.search.is_some() {
+            app.handle_search_key(key.code);
+            continue;
+        }
+        if app.command_palette.is_some() {
+            match app.handle_command_palette_key(key.code) {
+                Some(Action::Quit) => return Ok(()),
+                Some(action) => app.perform(action)?,
+                None => {}
+            }
+            continue;
+        }
+
+        let Some(action) = app.keymap.action(key) else {
+            continue;
+        };
+        if action == Action::Quit {
+            return Ok(());
         }
+        app.perform(action)?;
     }
 }
 
 fn ui(f: &mut Frame, app: &mut JTermApp) {
-    if app.show_map {
-        render_map_view(f, app);
-    } else if app.show_stats {
-        render_stats_view(f, app);
-    } else if app.show_alt_map {
-        render_alt_map_view(f, app);
-    } else {
-        render_list_view(f, app);
+    app.areas = ScreenAreas::default();
+    // Views only set the colors that differ from the theme's base
+    f.render_widget(Block::default().style(app.theme.base()), f.area());
+
+    match app.view {
+        View::List => render_list_view(f, app),
+        View::RegionMap => render_map_view(f, app),
+        View::Stats => render_stats_view(f, app),
+        View::Overview => render_alt_map_view(f, app),
+        View::Journal => render_journal_view(f, app),
     }
     
     // Render detail popup if active
     if app.show_detail {
         render_detail_popup(f, app);
     }
+
+    if app.visit_form.is_some() {
+        render_visit_form(f, app);
+    }
+
+    if app.export_prompt.is_some() {
+        render_export_prompt(f, app);
+    }
+
+    if app.tag_prompt.is_some() {
+        render_tag_prompt(f, app);
+    }
+
+    if app.list_menu.is_some() {
+        render_list_menu(f, app);
+    }
+
+    if app.profile_menu.is_some() {
+        render_profile_menu(f, app);
+    }
+
+    if app.command_palette.is_some() {
+        render_command_palette(f, app);
+    }
+
+    render_status_line(f, app);
+    render_search_bar(f, app);
+
+    if app.ascii {
+        glyphs::asciify_buffer(f.buffer_mut());
+    }
+}
+
+// "[alice] Japanese Prefectures · unvisited · by area (12 of 47)"
+fn list_title(app: &JTermApp, shown: usize) -> String {
+    let mut title = "Japanese Prefectures".to_string();
+    if app.profile != storage::DEFAULT_PROFILE {
+        title = format!("[{}] {}", app.profile, title);
+    }
+    let summary = app.settings.list.describe();
+    if !summary.is_empty() {
+        title.push_str(&format!(" · {}", summary));
+    }
+    if app.ascii {
+        title = title.replace('≥', ">=").replace('≤', "<=");
+    }
+    if shown < app.prefectures.len() {
+        title.push_str(&format!(" ({} of {})", shown, app.prefectures.len()));
+    }
+    title
 }
 
 fn render_list_view(f: &mut Frame, app: &mut JTermApp) {
@@ -853,30 +1831,35 @@ fn render_list_view(f: &mut Frame, app: &mut JTermApp) {
         .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
         .split(f.area());
 
-    let prefecture_items: Vec<ListItem> = app
-        .prefectures
+    let visible = app.visible_prefectures();
+    let prefecture_items: Vec<ListItem> = visible
         .iter()
-        .map(|prefecture| {
-            let level = app.get_prefecture_level(&prefecture.name_en);
+        .map(|&index| {
+            let prefecture = &app.prefectures[index];
+            let level = app.get_prefecture_level(&prefecture.iso_code);
             
             ListItem::new(format!(
                 "{} ({}) - Level {}",
                 prefecture.name_en, prefecture.name_jp, level
             ))
-            .style(Style::default().fg(JTermApp::get_level_color(level)))
+            .style(Style::default().fg(app.theme.level(level)))
         })
         .collect();
 
+    let list_block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(border::ROUNDED)
+        .title(list_title(app, visible.len()));
+    app.areas.list = list_block.inner(chunks[0]);
+    app.list_state.select(visible.iter().position(|&index| index == app.selected_index));
+
     let prefectures_list = List::new(prefecture_items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_set(border::ROUNDED)
-                .title("Japanese Prefectures")
-        )
-        .highlight_style(Style::default().add_modifier(Modifier::BOLD).bg(Color::DarkGray));
+        .block(list_block)
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).bg(app.theme.selection));
 
-    f.render_stateful_widget(prefectures_list, chunks[0], &mut app.list_state.clone());
+    // Rendered with the app's own state so the scroll offset ratatui picks
+    // is kept, and clicks can be mapped back to rows
+    f.render_stateful_widget(prefectures_list, chunks[0], &mut app.list_state);
 
     let right_chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -884,16 +1867,20 @@ fn render_list_view(f: &mut Frame, app: &mut JTermApp) {
         .split(chunks[1]);
 
     if let Some(selected_prefecture) = app.prefectures.get(app.selected_index) {
-        let level = app.get_prefecture_level(&selected_prefecture.name_en);
+        let level = app.get_prefecture_level(&selected_prefecture.iso_code);
         let level_text = JTermApp::get_level_text(level);
 
+        let tags = app.user_progress.tags_for(&selected_prefecture.iso_code);
         let info_text = format!(
-            "Prefecture: {}\nJapanese: {}\nRegion: {}\n\nCurrent Level: {} - {}\n\nPress 0-5 to set experience level",
+            "Prefecture: {}\nJapanese: {}\nRegion: {}\nTags: {}\n\nCurrent Level: {} - {} ({})\n\nPress {} to set experience level",
             selected_prefecture.name_en,
             selected_prefecture.name_jp,
             selected_prefecture.region,
+            if tags.is_empty() { "-".to_string() } else { tags.join(", ") },
             level,
-            level_text
+            level_text,
+            keikenchi::level_name(level),
+            app.keymap.hint(&LEVELS)
         );
 
         let info_paragraph = Paragraph::new(info_text)
@@ -903,18 +1890,52 @@ fn render_list_view(f: &mut Frame, app: &mut JTermApp) {
                     .border_set(border::ROUNDED)
                     .title("Prefecture Info")
             )
-            .style(Style::default().fg(FlexokiTheme::TX))
+            .style(Style::default().fg(app.theme.text))
             .wrap(Wrap { trim: true });
 
         f.render_widget(info_paragraph, right_chunks[0]);
     }
 
     let help_text = if app.show_help {
-        "Controls:\n\n↑/↓ or j/k: Navigate\nEnter: Show prefecture details\n0-5: Set experience level\nm: Toggle map view\nw: Toggle overview map\ns: Toggle stats view\nh/F1: Toggle this help\nq: Quit\n\nLevels:\n0: Never been there (⬜)\n1: Passed there (🟥)\n2: Alighted there (🟨)\n3: Visited there (🟩)\n4: Stayed there (🟪)\n5: Lived there (🟦)"
+        let rows: &[HelpRow] = &[
+            (&[Action::Up, Action::Down], "Navigate"),
+            (&[Action::Search], "Search by name, reading or region"),
+            (&[Action::Filter], "Filter and sort the list"),
+            (&[Action::Tags], "Edit tags"),
+            (&[Action::Profiles], "Switch profile"),
+            (&[Action::NextTheme], "Next color theme"),
+            (&[Action::NextPalette], "Colorblind-safe level colors"),
+            (&[Action::TogglePatterns], "Level glyphs and patterns"),
+            (&[Action::ToggleAscii], "ASCII mode"),
+            (&[Action::Open], "Show prefecture details"),
+            (&[], "Click: Select, wheel: Scroll"),
+            (&LEVELS, "Set experience level"),
+            (&[Action::Undo, Action::Redo], "Undo / redo"),
+            (&[Action::LogVisit], "Log a visit with notes"),
+            (&[Action::MapView], "Toggle map view"),
+            (&[Action::OverviewView], "Toggle overview map"),
+            (&[Action::StatsView], "Toggle stats view"),
+            (&[Action::JournalView], "Toggle travel journal"),
+            (&[Action::Palette], "Command palette"),
+            (&[Action::Help], "Toggle this help"),
+            (&[Action::Quit], "Quit"),
+        ];
+        help_lines(app, "Controls:", rows) + "\nLevels:"
     } else {
-        "Press 'h' for help, 'm' for map, 'w' for overview\n's' for stats, Enter for details, 0-5 for levels"
+        brief_help(app, &[
+            &[(&[Action::Palette], "all commands")],
+            &[(&[Action::Help], "help"), (&[Action::MapView], "map"), (&[Action::OverviewView], "overview")],
+            &[(&[Action::StatsView], "stats"), (&[Action::JournalView], "journal"), (&[Action::Search], "search")],
+            &[(&[Action::Filter], "filter and sort"), (&[Action::Tags], "tags")],
+            &[(&[Action::Open], "details"), (&LEVELS, "levels"), (&[Action::Undo], "undo")],
+        ])
     };
 
+    let mut help_text = ratatui::text::Text::from(help_text);
+    if app.show_help {
+        help_text.extend(level_legend(app));
+    }
+
     let help_paragraph = Paragraph::new(help_text)
         .block(
             Block::default()
@@ -945,414 +1966,928 @@ fn render_stats_view(f: &mut Frame, app: &mut JTermApp) {
     let visited_count = stats.total_prefectures - stats.level_counts[0];
     let completion_percentage = (visited_count as f64 / stats.total_prefectures as f64 * 100.0) as u32;
 
-    // Create colorful progress bar segments
-    let bar_width: usize = 20;
-    let filled_segments = (completion_percentage / 5) as usize;
-    
-    // Create colored progress bar characters based on completion percentage
-    let (filled_char, empty_char) = if completion_percentage < 20 {
-        ("🟥", "⬜") // Red for less than 20%
-    } else if completion_percentage < 50 {
-        ("🟨", "⬜") // Yellow for 20-49%
-    } else if completion_percentage < 75 {
-        ("🟦", "⬜") // Blue for 50-74%
+    // Progress bar colored by how far along it is
+    let bar_width: usize = 20;
+    let filled_segments = (completion_percentage / 5) as usize;
+    let progress_bar = ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            "█".repeat(filled_segments),
+            Style::default().fg(app.theme.progress(completion_percentage)),
+        ),
+        ratatui::text::Span::styled(
+            "░".repeat(bar_width.saturating_sub(filled_segments)),
+            Style::default().fg(app.theme.muted),
+        ),
+        ratatui::text::Span::raw(format!("  {}%", completion_percentage)),
+    ]);
+
+    let overall_text = format!(
+        "📊 TRAVEL STATISTICS\n\n\
+        Total Prefectures: {}\n\
+        Visited: {} / {} ({}%)\n\
+        経県値 (Keikenchi): {}\n\
+        Max Possible: {}",
+        stats.total_prefectures,
+        visited_count,
+        stats.total_prefectures,
+        completion_percentage,
+        stats.total_score,
+        stats.total_prefectures * 5
+    );
+    let mut overall_text = ratatui::text::Text::from(app.plain(&overall_text));
+    overall_text.push_line("");
+    overall_text.push_line(progress_bar);
+
+    let overall_paragraph = Paragraph::new(overall_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border::ROUNDED)
+                .title("Overall Progress")
+        )
+        .style(Style::default().fg(app.theme.text))
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(overall_paragraph, top_chunks[0]);
+
+    // Level breakdown, each line keyed by its level's color
+    let level_rows = [
+        (5, "🏠 Lived there"),
+        (4, "🏨 Stayed there"),
+        (3, "🚶 Visited there"),
+        (2, "🚂 Alighted there"),
+        (1, "🚗 Passed there"),
+        (0, "❌ Never been"),
+    ];
+    let mut level_text = ratatui::text::Text::from(app.plain("📈 EXPERIENCE BREAKDOWN"));
+    level_text.push_line("");
+    for (level, label) in level_rows {
+        level_text.push_line(ratatui::text::Line::from(vec![
+            app.level_swatch(level),
+            ratatui::text::Span::raw(format!(
+                " {} {} ({}): {}",
+                app.plain(label),
+                keikenchi::level_name(level),
+                level,
+                stats.level_counts[level as usize]
+            )),
+        ]));
+    }
+    level_text.push_line("");
+    level_text.push_line(format!(
+        "Most Common: Level {}",
+        stats.level_counts.iter().enumerate().max_by_key(|(_, count)| *count).unwrap().0
+    ));
+
+    let level_paragraph = Paragraph::new(level_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border::ROUNDED)
+                .title("Level Breakdown")
+        )
+        .style(Style::default().fg(app.theme.info))
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(level_paragraph, top_chunks[1]);
+
+    // Regional breakdown
+    let bottom_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(chunks[1]);
+
+    let mut region_lines = vec![ratatui::text::Line::from(app.plain("🗾 REGIONAL PROGRESS")), ratatui::text::Line::default()];
+    
+    for region in &app.regions {
+        if let Some((visited, total)) = stats.region_stats.get(&region.name) {
+            let percentage = (*visited as f64 / *total as f64 * 100.0) as u32;
+            let bar_filled = (percentage / 8) as usize; // Smaller bars for better fit
+            let bar_empty = 12 - bar_filled; // 12-char wide bars
+            
+            region_lines.push(ratatui::text::Line::from(app.plain(&format!(
+                "{} {}: {}/{} ({}%)",
+                region.emoji, region.name, visited, total, percentage
+            ))));
+            
+            // Color-coded progress bars based on completion
+            region_lines.push(ratatui::text::Line::from(vec![
+                ratatui::text::Span::styled("█".repeat(bar_filled), Style::default().fg(app.theme.progress(percentage))),
+                ratatui::text::Span::styled("░".repeat(bar_empty), Style::default().fg(app.theme.muted)),
+            ]));
+            region_lines.push(ratatui::text::Line::default()); // Add spacing
+        }
+    }
+
+    let region_block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(border::ROUNDED)
+        .title("Regional Breakdown");
+    app.areas.stats_regions = region_block.inner(bottom_chunks[0]);
+
+    // Measure the wrapped text so scrolling stops at its last line
+    let region_paragraph = Paragraph::new(region_lines)
+        .style(Style::default().fg(app.theme.success))
+        .wrap(Wrap { trim: true });
+    app.areas.stats_lines = region_paragraph.line_count(app.areas.stats_regions.width);
+    app.stats_scroll = app.stats_scroll.min(app.max_stats_scroll());
+
+    let region_paragraph = region_paragraph
+        .block(region_block)
+        .scroll((app.stats_scroll, 0));
+
+    f.render_widget(region_paragraph, bottom_chunks[0]);
+
+    // Help section
+    let help_text = if app.show_help {
+        let rows: &[HelpRow] = &[
+            (&[Action::Up, Action::Down], "Navigate/scroll"),
+            (&LEVELS, "Set experience level"),
+            (&[Action::Undo, Action::Redo], "Undo / redo"),
+            (&[Action::StatsView], "Back to list view"),
+            (&[Action::MapView], "Map view"),
+            (&[Action::Palette], "Command palette"),
+            (&[Action::Help], "Toggle this help"),
+            (&[Action::Export(ExportFormat::Json)], "Export to JSON"),
+            (&[Action::Export(ExportFormat::Csv)], "Export to CSV"),
+            (&[Action::Export(ExportFormat::Svg)], "Export map as SVG"),
+            (&[Action::Export(ExportFormat::Png)], "Export map as PNG"),
+            (&[Action::Export(ExportFormat::Keikenchi)], "Export 経県値 share text"),
+            (&[Action::Quit], "Quit"),
+        ];
+        help_lines(app, "Stats View Controls:", rows)
+            + "\nExports ask where to save the file\nYour progress is automatically saved!"
+    } else {
+        brief_help(app, &[
+            &[(&[Action::StatsView], "list view")],
+            &[(&[Action::MapView], "map view")],
+            &[(&[Action::Help], "help")],
+            &[(&[Action::Palette], "all commands")],
+            &[(&[Action::Export(ExportFormat::Json)], "export JSON")],
+            &[(&[Action::Export(ExportFormat::Csv)], "export CSV")],
+            &[(&[Action::Export(ExportFormat::Svg), Action::Export(ExportFormat::Png)], "export map SVG/PNG")],
+            &[(&[Action::Export(ExportFormat::Keikenchi)], "export 経県値 text")],
+        ]) + "\n\nKeep exploring Japan! 🗾"
+    };
+
+    let help_paragraph = Paragraph::new(app.plain(&help_text))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border::ROUNDED)
+                .title("Help")
+        )
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(help_paragraph, bottom_chunks[1]);
+}
+
+fn render_alt_map_view(f: &mut Frame, app: &mut JTermApp) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .margin(1)
+        .constraints([Constraint::Percentage(75), Constraint::Percentage(25)].as_ref())
+        .split(f.area());
+    
+    // Render the choropleth image if the terminal supports it
+    app.refresh_map_image();
+    let map_title = app.plain("🗾 Japan Progress Map");
+    if let Some(ref mut image) = app.japan_map_image {
+        let map_block = Block::default()
+            .borders(Borders::ALL)
+            .border_set(border::ROUNDED)
+            .title(map_title);
+        
+        // Calculate inner area for the image (inside the border)
+        let inner_area = map_block.inner(chunks[0]);
+        f.render_widget(map_block, chunks[0]);
+        f.render_stateful_widget(StatefulImage::new(None), inner_area, image);
+        if let Some(picker) = &app.image_picker {
+            app.areas.overview_image = fitted_image_area(app.japan_map_size, picker.font_size, inner_area);
+        }
+        
+        // Create prefecture list sidebar
+        render_prefecture_sidebar(f, app, chunks[1]);
+    } else {
+        // Fallback to the original colored squares implementation
+        let mut map_grid = vec![vec![ratatui::text::Span::raw(" "); 60]; 20];
+        
+        // Place each prefecture at its map position from the dataset
+        for prefecture in &app.prefectures {
+            let (row, col) = (prefecture.map_pos.0 as usize, prefecture.map_pos.1 as usize);
+            if let Some(cell) = map_grid.get_mut(row).and_then(|r| r.get_mut(col)) {
+                *cell = app.level_swatch(app.get_prefecture_level(&prefecture.iso_code));
+            }
+        }
+        
+        // Convert grid to lines
+        let map_lines: Vec<ratatui::text::Line> =
+            map_grid.iter().map(|row| ratatui::text::Line::from(row.clone())).collect();
+        
+        let map_block = Block::default()
+            .borders(Borders::ALL)
+            .border_set(border::ROUNDED)
+            .title(app.plain("🗾 Japan Overview Map (Fallback)"));
+        let inner_area = map_block.inner(chunks[0]);
+
+        // Record where each square landed. Squares are two cells wide, so a
+        // square's column depends on how many squares precede it in its row.
+        for (index, prefecture) in app.prefectures.iter().enumerate() {
+            let (row, col) = (prefecture.map_pos.0 as usize, prefecture.map_pos.1 as usize);
+            let Some(cells) = map_grid.get(row).filter(|cells| col < cells.len()) else {
+                continue;
+            };
+            let x: usize = cells[..col].iter().map(|cell| cell.width()).sum();
+            let cell = Rect::new(inner_area.x + x as u16, inner_area.y + row as u16, 2, 1);
+            if inner_area.contains(cell.as_position()) {
+                app.areas.overview_grid.push((cell, index));
+            }
+        }
+
+        // Not wrapped so the squares stay where they were recorded
+        let map_paragraph = Paragraph::new(map_lines)
+            .block(map_block)
+            .style(Style::default().fg(app.theme.text));
+        
+        f.render_widget(map_paragraph, chunks[0]);
+        
+        // Create prefecture list sidebar for fallback too
+        render_prefecture_sidebar(f, app, chunks[1]);
+    }
+}
+
+fn render_prefecture_sidebar(f: &mut Frame, app: &mut JTermApp, area: ratatui::layout::Rect) {
+    // Create separate lines for each prefecture, in dataset order from
+    // Hokkaido to Okinawa, or best match first while searching
+    let visible = app.visible_prefectures();
+    let mut lines = Vec::new();
+    for &index in &visible {
+        let prefecture = &app.prefectures[index];
+        let level = app.get_prefecture_level(&prefecture.iso_code);
+        let level_text = match level {
+            // With glyphs on, ○ is level 1's
+            _ if app.settings.patterns => app.level_glyph(level),
+            0 => "○",
+            1 => "1", 
+            2 => "2",
+            3 => "3", 
+            4 => "4",
+            5 => "5",
+            _ => "?",
+        };
+        
+        let color = app.theme.level(level);
+        let text = format!("{} {}", level_text, prefecture.name_jp);
+        let mut style = Style::default().fg(color);
+        if index == app.selected_index {
+            style = style.add_modifier(Modifier::BOLD).bg(app.theme.selection);
+        }
+        
+        lines.push(ratatui::text::Line::from(vec![
+            ratatui::text::Span::styled(text, style)
+        ]));
+    }
+    
+    let sidebar_block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(border::ROUNDED)
+        .title(app.plain("🗾 Prefecture List"));
+    app.areas.sidebar = sidebar_block.inner(area);
+    // Follow the selection, however it was made
+    let visible_height = app.areas.sidebar.height.max(1);
+    if let Some(selected) = visible.iter().position(|&index| index == app.selected_index) {
+        let selected = selected as u16;
+        if selected < app.prefecture_scroll {
+            app.prefecture_scroll = selected;
+        } else if selected >= app.prefecture_scroll + visible_height {
+            app.prefecture_scroll = selected + 1 - visible_height;
+        }
+    }
+    app.prefecture_scroll = app.prefecture_scroll.min(app.max_prefecture_scroll());
+
+    // One line per prefecture, so a clicked row maps straight to an index
+    let prefecture_paragraph = Paragraph::new(lines)
+        .block(sidebar_block)
+        .scroll((app.prefecture_scroll, 0));
+    
+    f.render_widget(prefecture_paragraph, area);
+}
+
+fn render_journal_view(f: &mut Frame, app: &mut JTermApp) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .margin(1)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(f.area());
+
+    let journal = app.user_progress.journal();
+
+    let journal_items: Vec<ListItem> = journal
+        .iter()
+        .map(|(_, visit)| {
+            ListItem::new(format!(
+                "{:<23} {} - Level {}",
+                visit.date_label(),
+                app.prefecture_label(&visit.prefecture),
+                visit.level
+            ))
+            .style(Style::default().fg(app.theme.level(visit.level)))
+        })
+        .collect();
+
+    let mut journal_state = ListState::default();
+    if !journal.is_empty() {
+        journal_state.select(Some(app.journal_index.min(journal.len() - 1)));
+    }
+
+    let journal_list = List::new(journal_items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border::ROUNDED)
+                .title(format!("Travel Journal ({} visits)", journal.len()))
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).bg(app.theme.selection));
+
+    f.render_stateful_widget(journal_list, chunks[0], &mut journal_state);
+
+    let right_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(chunks[1]);
+
+    let entry_text = match journal_state.selected().and_then(|i| journal.get(i)) {
+        Some((_, visit)) => format!(
+            "Prefecture: {}\nDate: {}\nLevel: {} - {}\n\nCompanions: {}\n\nNotes:\n{}",
+            app.prefecture_label(&visit.prefecture),
+            visit.date_label(),
+            visit.level,
+            JTermApp::get_level_text(visit.level),
+            if visit.companions.is_empty() { "-".to_string() } else { visit.companions.join(", ") },
+            if visit.notes.is_empty() { "-" } else { &visit.notes }
+        ),
+        None => format!(
+            "No visits logged yet.\n\nSelect a prefecture in the list view and press {} to log one.",
+            app.keymap.hint(&[Action::LogVisit])
+        ),
+    };
+
+    let entry_paragraph = Paragraph::new(entry_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border::ROUNDED)
+                .title("Visit")
+        )
+        .style(Style::default().fg(app.theme.text))
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(entry_paragraph, right_chunks[0]);
+
+    let help_text = if app.show_help {
+        let rows: &[HelpRow] = &[
+            (&[Action::Up, Action::Down], "Navigate visits"),
+            (&[Action::Open], "Edit visit"),
+            (&[Action::LogVisit], "Log a visit"),
+            (&[Action::DeleteVisit], "Delete visit"),
+            (&[Action::Undo, Action::Redo], "Undo / redo"),
+            (&[Action::JournalView], "Back to list view"),
+            (&[Action::Palette], "Command palette"),
+            (&[Action::Help], "Toggle this help"),
+            (&[Action::Quit], "Quit"),
+        ];
+        help_lines(app, "Journal Controls:", rows)
+    } else {
+        brief_help(app, &[
+            &[(&[Action::JournalView], "list view")],
+            &[(&[Action::Open], "edit"), (&[Action::DeleteVisit], "delete")],
+            &[(&[Action::LogVisit], "log a visit")],
+            &[(&[Action::Palette], "all commands")],
+        ])
+    };
+
+    let help_paragraph = Paragraph::new(help_text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border::ROUNDED)
+                .title("Help")
+        )
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(help_paragraph, right_chunks[1]);
+}
+
+fn render_visit_form(f: &mut Frame, app: &mut JTermApp) {
+    let Some(form) = app.visit_form.as_ref() else {
+        return;
+    };
+
+    let area = f.area();
+    let popup_width = 64.min(area.width);
+    let popup_height = 16.min(area.height);
+    let popup_area = ratatui::layout::Rect {
+        x: (area.width.saturating_sub(popup_width)) / 2,
+        y: (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    clear_popup(f, app, popup_area);
+
+    let mut lines = Vec::new();
+    for (i, (label, value)) in VISIT_FORM_LABELS.iter().zip(form.fields.iter()).enumerate() {
+        let style = if i == form.focus {
+            Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(app.theme.text)
+        };
+        let cursor = if i == form.focus { "▏" } else { "" };
+        lines.push(ratatui::text::Line::styled(label.to_string(), Style::default().fg(app.theme.muted)));
+        lines.push(ratatui::text::Line::styled(format!("> {}{}", value, cursor), style));
+    }
+    lines.push(ratatui::text::Line::from(""));
+    match &form.error {
+        Some(err) => lines.push(ratatui::text::Line::styled(err.clone(), Style::default().fg(app.theme.error))),
+        None => lines.push(ratatui::text::Line::styled(
+            "Tab: next field  Enter: save  Esc: cancel",
+            Style::default().fg(app.theme.muted),
+        )),
+    }
+
+    let title = if form.editing.is_some() { "Edit Visit" } else { "Log Visit" };
+    let form_paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border::ROUNDED)
+                .title(format!("{} - {}", title, app.prefecture_label(&form.prefecture)))
+        )
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(form_paragraph, popup_area);
+}
+
+fn render_export_prompt(f: &mut Frame, app: &mut JTermApp) {
+    let Some(prompt) = app.export_prompt.as_ref() else {
+        return;
+    };
+
+    let area = f.area();
+    let popup_width = 72.min(area.width);
+    let popup_height = 7.min(area.height);
+    let popup_area = ratatui::layout::Rect {
+        x: (area.width.saturating_sub(popup_width)) / 2,
+        y: (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    clear_popup(f, app, popup_area);
+
+    let (hint, hint_color) = if prompt.confirm_overwrite {
+        ("File exists. Overwrite? (y/n)", app.theme.error)
     } else {
-        ("🟩", "⬜") // Green for 75%+
+        ("Enter: export  Esc: cancel", app.theme.muted)
     };
-    
-    let progress_bar = format!(
-        "{}{}",
-        filled_char.repeat(filled_segments),
-        empty_char.repeat(bar_width.saturating_sub(filled_segments))
-    );
 
-    let overall_text = format!(
-        "📊 TRAVEL STATISTICS\n\n\
-        Total Prefectures: {}\n\
-        Visited: {} / {} ({}%)\n\
-        Total Score: {}\n\
-        Max Possible: {}\n\n\
-        {}  {}%",
-        stats.total_prefectures,
-        visited_count,
-        stats.total_prefectures,
-        completion_percentage,
-        stats.total_score,
-        stats.total_prefectures * 5,
-        progress_bar,
-        completion_percentage
-    );
+    let lines = vec![
+        ratatui::text::Line::styled("Save to:", Style::default().fg(app.theme.muted)),
+        ratatui::text::Line::styled(
+            format!("> {}▏", prompt.path),
+            Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD),
+        ),
+        ratatui::text::Line::from(""),
+        ratatui::text::Line::styled(hint, Style::default().fg(hint_color)),
+    ];
 
-    let overall_paragraph = Paragraph::new(overall_text)
+    let prompt_paragraph = Paragraph::new(lines)
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_set(border::ROUNDED)
-                .title("Overall Progress")
+                .title(format!("Export {}", prompt.format.label()))
         )
-        .style(Style::default().fg(FlexokiTheme::TX))
-        .wrap(Wrap { trim: true });
+        .wrap(Wrap { trim: false });
 
-    f.render_widget(overall_paragraph, top_chunks[0]);
+    f.render_widget(prompt_paragraph, popup_area);
+}
 
-    // Level breakdown
-    let level_text = format!(
-        "📈 EXPERIENCE BREAKDOWN\n\n\
-        🏠 Lived there (5): {}\n\
-        🏨 Stayed there (4): {}\n\
-        🚶 Visited there (3): {}\n\
-        🚂 Alighted there (2): {}\n\
-        🚗 Passed there (1): {}\n\
-        ❌ Never been (0): {}\n\n\
-        Most Common: Level {}",
-        stats.level_counts[5],
-        stats.level_counts[4],
-        stats.level_counts[3],
-        stats.level_counts[2],
-        stats.level_counts[1],
-        stats.level_counts[0],
-        stats.level_counts.iter().enumerate().max_by_key(|(_, count)| *count).unwrap().0
-    );
+fn render_tag_prompt(f: &mut Frame, app: &mut JTermApp) {
+    let Some(prompt) = app.tag_prompt.as_ref() else {
+        return;
+    };
 
-    let level_paragraph = Paragraph::new(level_text)
+    let area = f.area();
+    let popup_width = 60.min(area.width);
+    let popup_height = 7.min(area.height);
+    let popup_area = ratatui::layout::Rect {
+        x: (area.width.saturating_sub(popup_width)) / 2,
+        y: (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    clear_popup(f, app, popup_area);
+
+    let lines = vec![
+        ratatui::text::Line::styled("Comma separated, e.g. onsen, ski", Style::default().fg(app.theme.muted)),
+        ratatui::text::Line::styled(
+            format!("> {}▏", prompt.text),
+            Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD),
+        ),
+        ratatui::text::Line::from(""),
+        ratatui::text::Line::styled("Enter: save  Esc: cancel", Style::default().fg(app.theme.muted)),
+    ];
+
+    let prompt_paragraph = Paragraph::new(lines)
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_set(border::ROUNDED)
-                .title("Level Breakdown")
+                .title(format!("Tags for {}", app.prefecture_label(&prompt.prefecture)))
         )
-        .style(Style::default().fg(FlexokiTheme::CY))
-        .wrap(Wrap { trim: true });
+        .wrap(Wrap { trim: false });
 
-    f.render_widget(level_paragraph, top_chunks[1]);
+    f.render_widget(prompt_paragraph, popup_area);
+}
 
-    // Regional breakdown
-    let bottom_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-        .split(chunks[1]);
+fn render_list_menu(f: &mut Frame, app: &mut JTermApp) {
+    let Some(focused) = app.list_menu else {
+        return;
+    };
 
-    let mut region_lines = vec!["🗾 REGIONAL PROGRESS\n".to_string()];
-    
-    // Define region order for better geographical organization
-    let region_order = vec!["Hokkaido", "Tohoku", "Kanto", "Chubu", "Kansai", "Chugoku", "Shikoku", "Kyushu", "Okinawa"];
-    
-    for region_name in region_order {
-        if let Some((visited, total)) = stats.region_stats.get(region_name) {
-            let percentage = (*visited as f64 / *total as f64 * 100.0) as u32;
-            let bar_filled = (percentage / 8) as usize; // Smaller bars for better fit
-            let bar_empty = 12 - bar_filled; // 12-char wide bars
-            
-            // Add region emoji for visual distinction
-            let region_emoji = match region_name {
-                "Hokkaido" => "❄️",
-                "Tohoku" => "🌸",
-                "Kanto" => "🏙️",
-                "Chubu" => "🏔️",
-                "Kansai" => "🏛️",
-                "Chugoku" => "🌊",
-                "Shikoku" => "🍊",
-                "Kyushu" => "🌋",
-                "Okinawa" => "🏝️",
-                _ => "🗾",
+    let area = f.area();
+    let popup_width = 52.min(area.width);
+    let popup_height = (LIST_MENU_LABELS.len() as u16 + 4).min(area.height);
+    let popup_area = ratatui::layout::Rect {
+        x: (area.width.saturating_sub(popup_width)) / 2,
+        y: (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    clear_popup(f, app, popup_area);
+
+    let options = &app.settings.list;
+    let level_label = |level: u8| format!("{} {}", level, JTermApp::get_level_text(level));
+    let values = [
+        level_label(options.min_level),
+        level_label(options.max_level),
+        options.region.clone().unwrap_or_else(|| "All regions".to_string()),
+        match &options.tag {
+            Some(tag) => format!("#{}", tag),
+            None if app.user_progress.tags.is_empty() => "All (no tags yet, press #)".to_string(),
+            None => "All tags".to_string(),
+        },
+        options.sort.label().to_string(),
+        if options.reverse { "reversed" } else { "normal" }.to_string(),
+    ];
+
+    let mut lines: Vec<ratatui::text::Line> = LIST_MENU_LABELS
+        .iter()
+        .zip(values)
+        .enumerate()
+        .map(|(row, (label, value))| {
+            let style = if row == focused {
+                Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD).bg(app.theme.selection)
+            } else {
+                Style::default().fg(app.theme.text)
             };
-            
-            region_lines.push(format!(
-                "{} {}: {}/{} ({}%)",
-                region_emoji, region_name, visited, total, percentage
+            ratatui::text::Line::styled(format!(" {:<14} ‹ {} ›", label, value), style)
+        })
+        .collect();
+    lines.push(ratatui::text::Line::from(""));
+    lines.push(ratatui::text::Line::styled(
+        "↑/↓: choose  ←/→: change  r: reset  Esc: done",
+        Style::default().fg(app.theme.muted),
+    ));
+
+    let menu_paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_set(border::ROUNDED)
+            .title("Filter and Sort")
+    );
+
+    f.render_widget(menu_paragraph, popup_area);
+}
+
+fn render_profile_menu(f: &mut Frame, app: &mut JTermApp) {
+    let Some(menu) = app.profile_menu.as_ref() else {
+        return;
+    };
+
+    let area = f.area();
+    let popup_width = 48.min(area.width);
+    let popup_height = (menu.profiles.len() as u16 + 6).min(area.height);
+    let popup_area = ratatui::layout::Rect {
+        x: (area.width.saturating_sub(popup_width)) / 2,
+        y: (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    clear_popup(f, app, popup_area);
+
+    let default = app.settings.default_profile.as_deref().unwrap_or(storage::DEFAULT_PROFILE);
+    let mut lines: Vec<ratatui::text::Line> = menu
+        .profiles
+        .iter()
+        .enumerate()
+        .map(|(row, name)| {
+            let marker = if *name == app.profile { "●" } else { " " };
+            let note = if name == default { "  (default)" } else { "" };
+            let style = if row == menu.selected && menu.new_name.is_none() {
+                Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD).bg(app.theme.selection)
+            } else {
+                Style::default().fg(app.theme.text)
+            };
+            ratatui::text::Line::styled(format!(" {} {}{}", marker, name, note), style)
+        })
+        .collect();
+
+    lines.push(ratatui::text::Line::from(""));
+    match &menu.new_name {
+        Some(name) => {
+            lines.push(ratatui::text::Line::styled(
+                format!("New profile: {}▏", name),
+                Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD),
             ));
-            
-            // Color-coded progress bars based on completion
-            let bar_color = if percentage >= 80 { "🟢" } else if percentage >= 60 { "🟡" } else if percentage >= 40 { "🟠" } else { "🔴" };
-            
-            region_lines.push(format!(
-                "{}{}{}",
-                bar_color,
-                "█".repeat(bar_filled),
-                "░".repeat(bar_empty)
+            lines.push(ratatui::text::Line::styled(
+                "Enter: create and switch  Esc: cancel",
+                Style::default().fg(app.theme.muted),
+            ));
+        }
+        None => {
+            lines.push(ratatui::text::Line::styled(
+                "Enter: switch  n: new  d: make default",
+                Style::default().fg(app.theme.muted),
             ));
-            region_lines.push("".to_string()); // Add spacing
+            lines.push(ratatui::text::Line::styled("Esc: close", Style::default().fg(app.theme.muted)));
         }
     }
 
-    let region_text = region_lines.join("\n");
+    let menu_paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_set(border::ROUNDED)
+            .title("Profiles")
+    );
 
-    let region_paragraph = Paragraph::new(region_text)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_set(border::ROUNDED)
-                .title("Regional Breakdown")
-        )
-        .style(Style::default().fg(FlexokiTheme::GR))
-        .wrap(Wrap { trim: true })
-        .scroll((app.stats_scroll, 0));
+    f.render_widget(menu_paragraph, popup_area);
+}
 
-    f.render_widget(region_paragraph, bottom_chunks[0]);
+fn render_command_palette(f: &mut Frame, app: &mut JTermApp) {
+    let Some(palette) = app.command_palette.as_ref() else {
+        return;
+    };
 
-    // Help section
-    let help_text = if app.show_help {
-        "Stats View Controls:\n\n↑/↓ or j/k: Navigate/scroll\n0-5: Set experience level\ns: Back to list view\nm: Map view\nh/F1: Toggle this help\ne: Export to JSON\nx: Export to CSV\nq: Quit\n\nExports saved to home directory\nYour progress is automatically saved!"
-    } else {
-        "Press 's' for list view\nPress 'm' for map view\nPress 'h' for help\ne: Export JSON\nx: Export CSV\n\nKeep exploring Japan! 🗾"
+    let area = f.area();
+    let popup_width = 60.min(area.width);
+    let popup_height = 20.min(area.height);
+    let popup_area = ratatui::layout::Rect {
+        x: (area.width.saturating_sub(popup_width)) / 2,
+        y: (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
     };
 
-    let help_paragraph = Paragraph::new(help_text)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_set(border::ROUNDED)
-                .title("Help")
-        )
-        .wrap(Wrap { trim: true });
+    clear_popup(f, app, popup_area);
 
-    f.render_widget(help_paragraph, bottom_chunks[1]);
-}
+    // Query, blank line, results, blank line and the hint inside the border
+    let inner_width = popup_width.saturating_sub(2) as usize;
+    let rows = popup_height.saturating_sub(6) as usize;
+    let first = palette.selected.saturating_sub(rows.saturating_sub(1));
 
-fn render_alt_map_view(f: &mut Frame, app: &mut JTermApp) {
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .margin(1)
-        .constraints([Constraint::Percentage(75), Constraint::Percentage(25)].as_ref())
-        .split(f.area());
-    
-    // Try to render the SVG image if available
-    if let Some(ref mut image) = app.japan_map_image {
-        let map_block = Block::default()
-            .borders(Borders::ALL)
-            .border_set(border::ROUNDED)
-            .title("🗾 Japan Reference Map");
-        
-        // Calculate inner area for the image (inside the border)
-        let inner_area = map_block.inner(chunks[0]);
-        f.render_widget(map_block, chunks[0]);
-        f.render_stateful_widget(StatefulImage::new(None), inner_area, image);
-        
-        // Create prefecture list sidebar
-        render_prefecture_sidebar(f, app, chunks[1]);
-    } else {
-        // Fallback to the original colored squares implementation
-        let mut map_grid = vec![vec![" ".to_string(); 60]; 20];
-        
-        // Helper function to get colored square for prefecture
-        let get_color_square = |name: &str| -> String {
-            let level = app.get_prefecture_level(name);
-            match level {
-                0 => "⬜".to_string(),
-                1 => "🟥".to_string(),
-                2 => "🟨".to_string(),
-                3 => "🟩".to_string(),
-                4 => "🟪".to_string(),
-                5 => "🟦".to_string(),
-                _ => "⬜".to_string(),
+    let mut lines = vec![
+        ratatui::text::Line::styled(
+            format!(":{}▏", palette.query),
+            Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD),
+        ),
+        ratatui::text::Line::from(""),
+    ];
+    if palette.matches.is_empty() {
+        lines.push(ratatui::text::Line::styled(" No matching commands", Style::default().fg(app.theme.muted)));
+    }
+    for (row, &index) in palette.matches.iter().enumerate().skip(first).take(rows) {
+        let entry = &palette.entries[index];
+        let label = format!(" {}", app.plain(&entry.label));
+        // Key hints, right-aligned; profiles have none
+        let keys = match entry.target {
+            PaletteTarget::Action(action) if app.keymap.is_bound(&[action]) => {
+                format!("{} ", app.keymap.hint(&[action]))
             }
+            _ => String::new(),
         };
-        
-        // Shift everything right by ~15 spaces to center the map
-        let offset_x = 15;
-        
-        // Hokkaido (far north, centered)
-        map_grid[1][30 + offset_x] = get_color_square("Hokkaido");
-        
-        // Tohoku (northern Honshu, spread horizontally)
-        map_grid[3][28 + offset_x] = get_color_square("Aomori");
-        map_grid[4][32 + offset_x] = get_color_square("Iwate");
-        map_grid[4][24 + offset_x] = get_color_square("Akita");
-        map_grid[5][28 + offset_x] = get_color_square("Miyagi");
-        map_grid[5][24 + offset_x] = get_color_square("Yamagata");
-        map_grid[6][28 + offset_x] = get_color_square("Fukushima");
-        
-        // Kanto (Tokyo area, horizontally spread)
-        map_grid[7][24 + offset_x] = get_color_square("Tochigi");
-        map_grid[7][28 + offset_x] = get_color_square("Ibaraki");
-        map_grid[7][20 + offset_x] = get_color_square("Gunma");
-        map_grid[8][22 + offset_x] = get_color_square("Saitama");
-        map_grid[8][26 + offset_x] = get_color_square("Tokyo");
-        map_grid[8][30 + offset_x] = get_color_square("Chiba");
-        map_grid[9][26 + offset_x] = get_color_square("Kanagawa");
-        
-        // Chubu (central Japan, wide spread)
-        map_grid[6][18 + offset_x] = get_color_square("Niigata");
-        map_grid[8][14 + offset_x] = get_color_square("Toyama");
-        map_grid[8][10 + offset_x] = get_color_square("Ishikawa");
-        map_grid[9][10 + offset_x] = get_color_square("Fukui");
-        map_grid[8][18 + offset_x] = get_color_square("Nagano");
-        map_grid[9][22 + offset_x] = get_color_square("Yamanashi");
-        map_grid[9][14 + offset_x] = get_color_square("Gifu");
-        map_grid[10][22 + offset_x] = get_color_square("Shizuoka");
-        map_grid[10][14 + offset_x] = get_color_square("Aichi");
-        
-        // Kansai (Kyoto/Osaka area, spread wide)
-        map_grid[10][10 + offset_x] = get_color_square("Mie");
-        map_grid[9][8 + offset_x] = get_color_square("Shiga");
-        map_grid[8][6 + offset_x] = get_color_square("Kyoto");
-        map_grid[9][4 + offset_x] = get_color_square("Osaka");
-        map_grid[9][2 + offset_x] = get_color_square("Hyogo");
-        map_grid[10][6 + offset_x] = get_color_square("Nara");
-        map_grid[11][4 + offset_x] = get_color_square("Wakayama");
-        
-        // Chugoku (western Honshu, very wide)
-        map_grid[8][2 + offset_x] = get_color_square("Tottori");
-        map_grid[10][0 + offset_x] = get_color_square("Shimane");
-        map_grid[10][2 + offset_x] = get_color_square("Okayama");
-        map_grid[11][2 + offset_x] = get_color_square("Hiroshima");
-        map_grid[12][0 + offset_x] = get_color_square("Yamaguchi");
-        
-        // Shikoku (southern island, horizontally spread)
-        map_grid[12][4 + offset_x] = get_color_square("Kagawa");
-        map_grid[12][8 + offset_x] = get_color_square("Tokushima");
-        map_grid[12][2 + offset_x] = get_color_square("Ehime");
-        map_grid[13][4 + offset_x] = get_color_square("Kochi");
-        
-        // Kyushu (southwestern island, wide cluster)
-        map_grid[14][0 + offset_x] = get_color_square("Fukuoka");
-        map_grid[15][0 + offset_x] = get_color_square("Saga");
-        map_grid[16][0 + offset_x] = get_color_square("Nagasaki");
-        map_grid[15][2 + offset_x] = get_color_square("Kumamoto");
-        map_grid[14][4 + offset_x] = get_color_square("Oita");
-        map_grid[16][2 + offset_x] = get_color_square("Miyazaki");
-        map_grid[17][0 + offset_x] = get_color_square("Kagoshima");
-        
-        // Okinawa (far south)
-        map_grid[19][0 + offset_x] = get_color_square("Okinawa");
-        
-        // Convert grid to string
-        let mut map_lines = Vec::new();
-        
-        for row in &map_grid {
-            let line: String = row.iter().cloned().collect();
-            map_lines.push(line);
+        let label = pad_to_width(&label, inner_width.saturating_sub(keys.width()));
+        let (label_style, keys_style) = if row == palette.selected {
+            let focused = Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD).bg(app.theme.selection);
+            (focused, focused)
+        } else {
+            (Style::default().fg(app.theme.text), Style::default().fg(app.theme.muted))
+        };
+        lines.push(ratatui::text::Line::from(vec![
+            ratatui::text::Span::styled(label, label_style),
+            ratatui::text::Span::styled(keys, keys_style),
+        ]));
+    }
+
+    let hint_row = popup_height.saturating_sub(3) as usize;
+    while lines.len() < hint_row {
+        lines.push(ratatui::text::Line::from(""));
+    }
+    lines.push(ratatui::text::Line::styled(
+        "↑/↓: choose  Enter: run  Esc: close",
+        Style::default().fg(app.theme.muted),
+    ));
+
+    let title = format!("Commands ({} of {})", palette.matches.len(), palette.entries.len());
+    let palette_paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_set(border::ROUNDED)
+            .title(title)
+    );
+
+    f.render_widget(palette_paragraph, popup_area);
+}
+
+// A line of a help panel: the keys for some actions and what they do in
+// that view. Rows without actions are shown as they are.
+type HelpRow<'a> = (&'a [Action], &'a str);
+
+// "keys: what they do" under `title` for each row, from the active keymap;
+// rows whose actions have no key are left out
+fn help_lines(app: &JTermApp, title: &str, rows: &[HelpRow]) -> String {
+    let mut text = format!("{}\n\n", title);
+    for &(actions, description) in rows {
+        if actions.is_empty() {
+            text.push_str(description);
+        } else if app.keymap.is_bound(actions) {
+            text.push_str(&format!("{}: {}", app.keymap.hint(actions), description));
+        } else {
+            continue;
         }
-        
-        let map_text = map_lines.join("\n");
-        
-        let map_paragraph = Paragraph::new(map_text)
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_set(border::ROUNDED)
-                    .title("🗾 Japan Overview Map (Fallback)")
-            )
-            .style(Style::default().fg(FlexokiTheme::TX))
-            .wrap(Wrap { trim: false });
-        
-        f.render_widget(map_paragraph, chunks[0]);
-        
-        // Create prefecture list sidebar for fallback too
-        render_prefecture_sidebar(f, app, chunks[1]);
+        text.push('\n');
     }
+    text
 }
 
-fn render_prefecture_sidebar(f: &mut Frame, app: &mut JTermApp, area: ratatui::layout::Rect) {
-    // Prefecture names in order from Hokkaido to Okinawa
-    let prefecture_order = vec![
-        // Hokkaido
-        "Hokkaido",
-        // Tohoku
-        "Aomori", "Iwate", "Akita", "Miyagi", "Yamagata", "Fukushima",
-        // Kanto
-        "Ibaraki", "Tochigi", "Gunma", "Saitama", "Tokyo", "Chiba", "Kanagawa",
-        // Chubu
-        "Niigata", "Toyama", "Ishikawa", "Fukui", "Yamanashi", "Nagano", "Gifu", "Shizuoka", "Aichi",
-        // Kansai
-        "Mie", "Shiga", "Kyoto", "Osaka", "Hyogo", "Nara", "Wakayama",
-        // Chugoku
-        "Tottori", "Shimane", "Okayama", "Hiroshima", "Yamaguchi",
-        // Shikoku
-        "Tokushima", "Kagawa", "Ehime", "Kochi",
-        // Kyushu
-        "Fukuoka", "Saga", "Nagasaki", "Kumamoto", "Oita", "Miyazaki", "Kagoshima",
-        // Okinawa
-        "Okinawa",
-    ];
+// The short help shown while the full one is off: a few "keys: what"
+// pairs per line
+fn brief_help(app: &JTermApp, lines: &[&[HelpRow]]) -> String {
+    lines
+        .iter()
+        .map(|rows| {
+            rows.iter()
+                .filter(|(actions, _)| app.keymap.is_bound(actions))
+                .map(|(actions, description)| format!("{}: {}", app.keymap.hint(actions), description))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-    // Create separate lines for each prefecture
-    let mut lines = Vec::new();
-    for prefecture_name in prefecture_order.iter() {
-        if let Some(prefecture) = app.prefectures.iter().find(|p| p.name_en == *prefecture_name) {
-            let level = app.get_prefecture_level(&prefecture.name_en);
-            let level_text = match level {
-                0 => "○",
-                1 => "1", 
-                2 => "2",
-                3 => "3", 
-                4 => "4",
-                5 => "5",
-                _ => "?",
-            };
-            
-            let color = JTermApp::get_level_color(level);
-            let text = format!("{} {}", level_text, prefecture.name_jp);
-            
-            lines.push(ratatui::text::Line::from(vec![
-                ratatui::text::Span::styled(text, Style::default().fg(color))
-            ]));
-        }
+// One "██ 3: Visited there" line per level in the theme's colors
+fn level_legend(app: &JTermApp) -> Vec<ratatui::text::Line<'static>> {
+    (0..=5)
+        .map(|level| {
+            ratatui::text::Line::from(vec![
+                app.level_swatch(level),
+                ratatui::text::Span::raw(format!(" {}: {}", level, JTermApp::get_level_text(level))),
+            ])
+        })
+        .collect()
+}
+
+// Blank the area under a popup, keeping the theme's background
+fn clear_popup(f: &mut Frame, app: &JTermApp, area: Rect) {
+    f.render_widget(ratatui::widgets::Clear, area);
+    f.render_widget(Block::default().style(app.theme.base()), area);
+}
+
+fn render_status_line(f: &mut Frame, app: &mut JTermApp) {
+    let Some(status) = app.status.as_ref() else {
+        return;
+    };
+
+    // The views leave a one-line margin; use the bottom one
+    let area = f.area();
+    if area.height < 2 {
+        return;
     }
-    
-    let prefecture_paragraph = Paragraph::new(lines)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_set(border::ROUNDED)
-                .title("🗾 Prefecture List")
-        )
-        .wrap(Wrap { trim: true })
-        .scroll((app.prefecture_scroll, 0));
-    
-    f.render_widget(prefecture_paragraph, area);
+    let status_area = ratatui::layout::Rect {
+        x: area.x + 1,
+        y: area.y + area.height - 1,
+        width: area.width.saturating_sub(2),
+        height: 1,
+    };
+
+    let color = if status.is_error { app.theme.error } else { app.theme.success };
+    let status_paragraph = Paragraph::new(status.text.as_str())
+        .style(Style::default().fg(color).add_modifier(Modifier::BOLD));
+
+    f.render_widget(status_paragraph, status_area);
 }
 
+fn render_search_bar(f: &mut Frame, app: &mut JTermApp) {
+    let Some(search) = app.search.as_ref() else {
+        return;
+    };
+
+    // Shares the bottom margin with the status line, which any key clears
+    let area = f.area();
+    if area.height < 2 {
+        return;
+    }
+    let search_area = ratatui::layout::Rect {
+        x: area.x + 1,
+        y: area.y + area.height - 1,
+        width: area.width.saturating_sub(2),
+        height: 1,
+    };
+
+    // Matches the list filters hide aren't counted
+    let (hint, color) = match app.visible_prefectures().len() {
+        0 => ("no matches".to_string(), app.theme.error),
+        1 => ("1 match".to_string(), app.theme.success),
+        n => (format!("{} matches", n), app.theme.success),
+    };
+    let search_line = ratatui::text::Line::from(vec![
+        ratatui::text::Span::styled(
+            format!("/{}▏", search.query),
+            Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD),
+        ),
+        ratatui::text::Span::styled(format!(" {}", hint), Style::default().fg(color)),
+        ratatui::text::Span::styled("  ↑/↓: choose  Enter: jump  Esc: cancel", Style::default().fg(app.theme.muted)),
+    ]);
+
+    f.render_widget(Paragraph::new(search_line), search_area);
+}
 
 fn render_detail_popup(f: &mut Frame, app: &mut JTermApp) {
     let area = f.area();
     
-    // Create a centered popup area
-    let popup_width = 60;
-    let popup_height = 20;
-    let x = (area.width.saturating_sub(popup_width)) / 2;
-    let y = (area.height.saturating_sub(popup_height)) / 2;
-    
+    // Create a centered popup area, cut down to fit small terminals
+    let popup_width = 60.min(area.width);
+    let popup_height = 20.min(area.height);
     let popup_area = ratatui::layout::Rect {
-        x,
-        y,
+        x: (area.width.saturating_sub(popup_width)) / 2,
+        y: (area.height.saturating_sub(popup_height)) / 2,
         width: popup_width,
         height: popup_height,
     };
     
     // Clear the background
-    f.render_widget(ratatui::widgets::Clear, popup_area);
+    clear_popup(f, app, popup_area);
     
-    let display_index = if app.show_map { app.map_selected_index } else { app.selected_index };
-    if let Some(prefecture) = app.prefectures.get(display_index) {
-        let level = app.get_prefecture_level(&prefecture.name_en);
+    if let Some(prefecture) = app.prefectures.get(app.selected_index) {
+        let level = app.get_prefecture_level(&prefecture.iso_code);
         let level_text = JTermApp::get_level_text(level);
-        let color = JTermApp::get_level_color(level);
+        let color = app.theme.level(level);
+        let visit_count = app.user_progress.visits_for(&prefecture.iso_code).count();
+        let last_visit = app
+            .user_progress
+            .last_visit(&prefecture.iso_code)
+            .map(|date| date.to_string())
+            .unwrap_or_else(|| "-".to_string());
         
         let detail_text = format!(
             "🏛️ PREFECTURE DETAILS\n\n\
             Name: {} ({})\n\
+            Code: {} (JIS {:02})\n\
             Region: {}\n\
             Capital: {}\n\
             Population: {}\n\
             Area: {} km²\n\
             Population Density: {:.1} people/km²\n\n\
             Travel Experience:\n\
-            Level {}: {}\n\n\
-            Press ESC to close\n\
-            Press 0-5 to change level",
+            Level {}: {} ({})\n\
+            Visits: {} (last: {})\n\n\
+            Press {} to close\n\
+            Press {} to change level, {} to log a visit",
             prefecture.name_en,
             prefecture.name_jp,
+            prefecture.iso_code,
+            prefecture.code,
             prefecture.region,
             prefecture.capital,
             prefecture.population,
             prefecture.area_km2,
             prefecture.population as f64 / prefecture.area_km2 as f64,
             level,
-            level_text
+            level_text,
+            keikenchi::level_name(level),
+            visit_count,
+            last_visit,
+            app.keymap.hint(&[Action::Close]),
+            app.keymap.hint(&LEVELS),
+            app.keymap.hint(&[Action::LogVisit])
         );
         
-        let popup_block = ratatui::widgets::Paragraph::new(detail_text)
+        let popup_block = ratatui::widgets::Paragraph::new(app.plain(&detail_text))
             .block(
                 ratatui::widgets::Block::default()
                     .borders(ratatui::widgets::Borders::ALL)
@@ -1374,29 +2909,29 @@ fn render_map_view(f: &mut Frame, app: &mut JTermApp) {
         .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
         .split(f.area());
 
+    let map_block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(border::ROUNDED);
+    app.areas.region_map = map_block.inner(chunks[0]);
+    // A taller terminal may now fit lines that were scrolled past
+    let max_scroll = app.max_map_scroll();
+    app.map_scroll = app.map_scroll.min(max_scroll);
+
     // Render the map with scrolling
-    let map_lines = app.render_map();
-    let visible_lines: Vec<String> = map_lines
-        .iter()
-        .skip(app.map_scroll as usize)
-        .cloned()
-        .collect();
-    let map_text = visible_lines.join("\n");
+    let map_lines = app.render_map().lines;
+    let visible_lines: Vec<ratatui::text::Line> = map_lines.into_iter().skip(app.map_scroll as usize).collect();
     
-    let scroll_indicator = if app.map_scroll > 0 || visible_lines.len() > 25 {
-        format!(" (Scroll: {} of {})", app.map_scroll + 1, map_lines.len().saturating_sub(25).max(1))
+    let scroll_indicator = if max_scroll > 0 {
+        format!(" (Scroll: {} of {})", app.map_scroll + 1, max_scroll + 1)
     } else {
         "".to_string()
     };
     
-    let map_paragraph = Paragraph::new(map_text)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_set(border::ROUNDED)
-                .title(format!("Japan Map - Organized by Region{}", scroll_indicator))
-        )
-        .wrap(Wrap { trim: false });
+    // Not wrapped: each map line must stay one screen row for scrolling
+    // and clicks to line up
+    let map_block = map_block.title(format!("Japan Map - Organized by Region{}", scroll_indicator));
+
+    let map_paragraph = Paragraph::new(visible_lines).block(map_block);
 
     f.render_widget(map_paragraph, chunks[0]);
 
@@ -1406,18 +2941,20 @@ fn render_map_view(f: &mut Frame, app: &mut JTermApp) {
         .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
         .split(chunks[1]);
 
-    if let Some(selected_prefecture) = app.prefectures.get(app.map_selected_index) {
-        let level = app.get_prefecture_level(&selected_prefecture.name_en);
+    if let Some(selected_prefecture) = app.prefectures.get(app.selected_index) {
+        let level = app.get_prefecture_level(&selected_prefecture.iso_code);
         let level_text = JTermApp::get_level_text(level);
 
         let info_text = format!(
-            "Selected:\n{} ({})\n\nRegion: {}\n\nLevel: {} - {}\n\nKanji: {}\n\nPress 0-5 to set level",
+            "Selected:\n{} ({})\n\nRegion: {}\n\nLevel: {} - {} ({})\n\nKanji: {}\n\nPress {} to set level",
             selected_prefecture.name_en,
             selected_prefecture.name_jp,
             selected_prefecture.region,
             level,
             level_text,
-            selected_prefecture.map_char
+            keikenchi::level_name(level),
+            selected_prefecture.map_char,
+            app.keymap.hint(&LEVELS)
         );
 
         let info_paragraph = Paragraph::new(info_text)
@@ -1427,18 +2964,49 @@ fn render_map_view(f: &mut Frame, app: &mut JTermApp) {
                     .border_set(border::ROUNDED)
                     .title("Prefecture Info")
             )
-            .style(Style::default().fg(FlexokiTheme::TX))
+            .style(Style::default().fg(app.theme.text))
             .wrap(Wrap { trim: true });
 
         f.render_widget(info_paragraph, right_chunks[0]);
     }
 
     let help_text = if app.show_help {
-        "Map View Controls:\n\n↑/↓ or j/k: Scroll map\n←/→: Select prefecture\nEnter: Show prefecture details\n0-5: Set experience level\nm: Toggle to list view\ns: Stats view\nh/F1: Toggle this help\nq: Quit\n\nEmoji colors show visit levels:\n⬜ Never 🟦 Passed/Alighted\n🟩 Visited 🟨 Stayed 🟥 Lived"
+        let rows: &[HelpRow] = &[
+            (&[Action::Up, Action::Down], "Scroll map"),
+            (&[Action::Left, Action::Right], "Select prefecture"),
+            (&[], "Click: Select prefecture"),
+            (&[Action::Search], "Search prefectures"),
+            (&[Action::Open], "Show prefecture details"),
+            (&LEVELS, "Set experience level"),
+            (&[Action::Undo, Action::Redo], "Undo / redo"),
+            (&[Action::MapView], "Toggle to list view"),
+            (&[Action::StatsView], "Stats view"),
+            (
+                &[Action::NextTheme, Action::NextPalette, Action::TogglePatterns, Action::ToggleAscii],
+                "Theme, colorblind colors, glyphs, ASCII",
+            ),
+            (&[Action::Palette], "Command palette"),
+            (&[Action::Help], "Toggle this help"),
+            (&[Action::Quit], "Quit"),
+        ];
+        help_lines(app, "Map View Controls:", rows) + "\nColors show visit levels:"
     } else {
-        "Press 'm' for list view\nPress 's' for stats\nPress 'h' for help\n\n↑/↓ scroll, ←/→ select\nEnter for details, 0-5 levels"
+        brief_help(app, &[
+            &[(&[Action::MapView], "list view")],
+            &[(&[Action::StatsView], "stats")],
+            &[(&[Action::Help], "help")],
+            &[(&[Action::Palette], "all commands")],
+            &[],
+            &[(&[Action::Up, Action::Down], "scroll"), (&[Action::Left, Action::Right], "select")],
+            &[(&[Action::Search], "search"), (&[Action::Open], "details"), (&LEVELS, "levels")],
+        ])
     };
 
+    let mut help_text = ratatui::text::Text::from(help_text);
+    if app.show_help {
+        help_text.extend(level_legend(app));
+    }
+
     let help_paragraph = Paragraph::new(help_text)
         .block(
             Block::default()
@@ -1450,3 +3018,73 @@ fn render_map_view(f: &mut Frame, app: &mut JTermApp) {
 
     f.render_widget(help_paragraph, right_chunks[1]);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::{parse_geo_data, BUNDLED_DATA};
+
+    fn line_text(line: &ratatui::text::Line) -> String {
+        line.spans.iter().map(|span| span.content.as_ref()).collect()
+    }
+
+    // The line each prefecture should land on, worked out from the layout
+    // alone: every region with members gets a header line, one line per
+    // member in dataset order and a footer, with a blank line in between
+    fn expected_lines(app: &JTermApp) -> Vec<usize> {
+        let mut lines = vec![usize::MAX; app.prefectures.len()];
+        let mut next = 0;
+        for region in &app.regions {
+            let members: Vec<usize> = (0..app.prefectures.len())
+                .filter(|&i| app.prefectures[i].region == region.name)
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+            if next > 0 {
+                next += 1;
+            }
+            for (position, index) in members.iter().enumerate() {
+                lines[*index] = next + 1 + position;
+            }
+            next += members.len() + 2;
+        }
+        lines
+    }
+
+    // Select each prefecture in turn and check that the row render_map
+    // reports for it, and the one line drawn with the marker, are where
+    // the layout puts it
+    fn assert_selection_rows_match(geo_data: GeoData) {
+        let mut app = JTermApp::with_data("test", geo_data, UserProgress::default(), Settings::default());
+        let expected = expected_lines(&app);
+        for (index, &line) in expected.iter().enumerate() {
+            app.select_prefecture(index);
+            let map = app.render_map();
+            let name = &app.prefectures[index].name_en;
+            assert_eq!(map.line_of(index), Some(line), "selection row for {}", name);
+            let marked: Vec<usize> = (0..map.lines.len())
+                .filter(|&i| line_text(&map.lines[i]).contains('►'))
+                .collect();
+            assert_eq!(marked, vec![line], "marked line for {}", name);
+        }
+    }
+
+    #[test]
+    fn selection_row_is_the_marked_line() {
+        assert_selection_rows_match(parse_geo_data(BUNDLED_DATA).unwrap());
+    }
+
+    #[test]
+    fn selection_row_follows_a_prefecture_into_another_region() {
+        let mut geo_data = parse_geo_data(BUNDLED_DATA).unwrap();
+        // Niigata is sometimes counted as Tohoku and Mie as Chubu; both
+        // move out of their dataset region and away from their neighbours
+        for (name, region) in [("Niigata", "Tohoku"), ("Mie", "Chubu")] {
+            let prefecture = geo_data.prefectures.iter_mut().find(|p| p.name_en == name).unwrap();
+            assert_ne!(prefecture.region, region);
+            prefecture.region = region.to_string();
+        }
+        assert_selection_rows_match(geo_data);
+    }
+}
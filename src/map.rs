@@ -0,0 +1,328 @@
+use image::{DynamicImage, RgbaImage};
+use ratatui::style::Color;
+use resvg::{tiny_skia, usvg};
+use std::sync::{Arc, OnceLock};
+use usvg::fontdb;
+
+// Prefecture outlines. Every <path> has the prefecture's ISO code as its id,
+// so colors are applied with a stylesheet rather than by editing the paths.
+pub const BUNDLED_MAP: &str = include_str!("../data/japan.svg");
+
+// resvg resolves the generic `sans-serif` to Arial only, so name common
+// fonts explicitly, CJK-capable ones first
+const FONT_FAMILIES: &str =
+    "Noto Sans CJK JP, Hiragino Sans, Yu Gothic, Noto Sans, DejaVu Sans, Helvetica, Arial, sans-serif";
+
+/// How a prefecture or legend swatch is painted. A pattern is drawn over
+/// the color in the outline color, so the map still reads without color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fill {
+    pub color: Color,
+    pub pattern: Option<Pattern>,
+}
+
+impl From<Color> for Fill {
+    fn from(color: Color) -> Self {
+        Fill { color, pattern: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern {
+    Dots,
+    Lines,
+    Diagonal,
+    Crosshatch,
+    Grid,
+}
+
+impl Pattern {
+    // Marks for one 24x24 tile, drawn in the tile's stroke and fill. Not
+    // paths, which the bundled map's stylesheet would restyle.
+    fn marks(self) -> &'static str {
+        match self {
+            Pattern::Dots => "<circle cx=\"12\" cy=\"12\" r=\"4\" stroke=\"none\"/>",
+            Pattern::Lines => "<line x1=\"0\" y1=\"12\" x2=\"24\" y2=\"12\"/>",
+            Pattern::Diagonal => {
+                "<line x1=\"-6\" y1=\"6\" x2=\"6\" y2=\"-6\"/><line x1=\"0\" y1=\"24\" x2=\"24\" y2=\"0\"/>\
+                 <line x1=\"18\" y1=\"30\" x2=\"30\" y2=\"18\"/>"
+            }
+            Pattern::Crosshatch => {
+                "<g stroke-width=\"2\"><line x1=\"0\" y1=\"24\" x2=\"24\" y2=\"0\"/><line x1=\"0\" y1=\"0\" x2=\"24\" y2=\"24\"/></g>"
+            }
+            Pattern::Grid => "<line x1=\"0\" y1=\"12\" x2=\"24\" y2=\"12\"/><line x1=\"12\" y1=\"0\" x2=\"12\" y2=\"24\"/>",
+        }
+    }
+
+    fn id(self) -> &'static str {
+        match self {
+            Pattern::Dots => "dots",
+            Pattern::Lines => "lines",
+            Pattern::Diagonal => "diagonal",
+            Pattern::Crosshatch => "crosshatch",
+            Pattern::Grid => "grid",
+        }
+    }
+}
+
+/// The bundled map with each listed prefecture filled in its color and
+/// outlined in `outline`.
+pub fn choropleth_svg(fills: &[(&str, Fill)], outline: Color) -> String {
+    let (head, body) = split_root(BUNDLED_MAP);
+    let defs = pattern_defs(fills.iter().map(|(_, fill)| *fill), outline);
+    format!("{}{}{}{}</svg>\n", head, defs, body, fill_style(fills, outline))
+}
+
+/// A standalone, shareable progress map: the choropleth on a paper
+/// background with a title, a summary line and a color legend drawn in the
+/// open sea at the top left. Text and outlines use `foreground`.
+pub fn progress_svg(
+    fills: &[(&str, Fill)],
+    background: Color,
+    foreground: Color,
+    title: &str,
+    summary: &str,
+    legend: &[(Fill, String)],
+) -> String {
+    let (head, body) = split_root(BUNDLED_MAP);
+
+    let ink = hex(foreground);
+    let mut overlay = format!("  <g font-family=\"{}\" fill=\"{}\">\n", FONT_FAMILIES, ink);
+    overlay.push_str(&format!(
+        "    <text x=\"40\" y=\"80\" font-size=\"56\" font-weight=\"bold\">{}</text>\n",
+        escape(title)
+    ));
+    overlay.push_str(&format!("    <text x=\"40\" y=\"140\" font-size=\"36\">{}</text>\n", escape(summary)));
+    for (i, (fill, label)) in legend.iter().enumerate() {
+        let y = 200 + i * 56;
+        overlay.push_str(&format!(
+            "    <rect x=\"40\" y=\"{}\" width=\"40\" height=\"40\" fill=\"{}\" stroke=\"{}\" stroke-width=\"3\"/>\n",
+            y,
+            paint(*fill),
+            ink
+        ));
+        overlay.push_str(&format!(
+            "    <text x=\"100\" y=\"{}\" font-size=\"32\">{}</text>\n",
+            y + 32,
+            escape(label)
+        ));
+    }
+    overlay.push_str("  </g>\n");
+
+    let defs = pattern_defs(
+        fills.iter().map(|(_, fill)| *fill).chain(legend.iter().map(|(fill, _)| *fill)),
+        foreground,
+    );
+    format!(
+        "{}{}\n  <rect width=\"100%\" height=\"100%\" fill=\"{}\"/>{}{}{}</svg>\n",
+        head,
+        defs,
+        hex(background),
+        body,
+        fill_style(fills, foreground),
+        overlay
+    )
+}
+
+// The root `<svg ...>` tag and everything between it and `</svg>`
+fn split_root(svg: &str) -> (&str, &str) {
+    let open = svg.find("<svg").unwrap_or(0);
+    let head_end = svg[open..].find('>').map_or(0, |i| open + i + 1);
+    let end = svg.rfind("</svg>").unwrap_or(svg.len());
+    (&svg[..head_end], &svg[head_end..end])
+}
+
+// Comes after the map's own stylesheet, so these rules win
+fn fill_style(fills: &[(&str, Fill)], outline: Color) -> String {
+    let mut style = format!("  <style>\n    path {{ stroke: {}; }}\n", hex(outline));
+    for (iso_code, fill) in fills {
+        style.push_str(&format!("    #{} {{ fill: {}; }}\n", iso_code, paint(*fill)));
+    }
+    style.push_str("  </style>\n");
+    style
+}
+
+// One <pattern> per distinct patterned fill, each a tile of the fill's
+// color with the pattern's marks in `ink`. The tile background is drawn
+// with crisp edges, or seams show between tiles.
+fn pattern_defs(fills: impl Iterator<Item = Fill>, ink: Color) -> String {
+    let mut seen = Vec::new();
+    let mut defs = String::new();
+    for fill in fills {
+        let Some(pattern) = fill.pattern else {
+            continue;
+        };
+        if seen.contains(&fill) {
+            continue;
+        }
+        seen.push(fill);
+        defs.push_str(&format!(
+            "  <pattern id=\"{}\" patternUnits=\"userSpaceOnUse\" width=\"24\" height=\"24\">\
+             <rect width=\"24\" height=\"24\" fill=\"{}\" shape-rendering=\"crispEdges\"/>\
+             <g stroke=\"{ink}\" stroke-width=\"3\" fill=\"{ink}\">{}</g></pattern>\n",
+            pattern_id(pattern, fill.color),
+            hex(fill.color),
+            pattern.marks(),
+            ink = hex(ink)
+        ));
+    }
+    if defs.is_empty() { defs } else { format!("\n  <defs>\n{}  </defs>", defs) }
+}
+
+fn pattern_id(pattern: Pattern, color: Color) -> String {
+    format!("{}-{}", pattern.id(), &hex(color)[1..])
+}
+
+// Value for a `fill` attribute or property
+fn paint(fill: Fill) -> String {
+    match fill.pattern {
+        Some(pattern) => format!("url(#{})", pattern_id(pattern, fill.color)),
+        None => hex(fill.color),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Rasterize an SVG document to `width` pixels wide, keeping its aspect
+/// ratio. Areas the SVG doesn't paint stay transparent.
+pub fn rasterize(svg: &str, width: u32) -> Result<DynamicImage, String> {
+    let mut options = usvg::Options::default();
+    // Only the export legend has text; skip the font scan for the plain map
+    if svg.contains("<text") {
+        options.fontdb = system_fonts();
+    }
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| e.to_string())?;
+    let size = tree.size();
+    let scale = width as f32 / size.width();
+    let height = (size.height() * scale).ceil() as u32;
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or("map image has zero size")?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    // tiny-skia stores premultiplied alpha, the image crate expects straight
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    let image = RgbaImage::from_raw(width, height, pixels).ok_or("map image buffer has the wrong size")?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+/// The prefecture drawn at a point given as fractions (0.0-1.0) of the
+/// map's width and height, for mouse hit-testing.
+pub fn prefecture_at(x: f32, y: f32) -> Option<&'static str> {
+    let outlines = outlines();
+    let (x, y) = (x * outlines.width, y * outlines.height);
+    outlines
+        .shapes
+        .iter()
+        .find(|(_, rings)| contains(rings, x, y))
+        .map(|(iso_code, _)| iso_code.as_str())
+}
+
+type Ring = Vec<(f32, f32)>;
+
+// viewBox size and the polygons of every path in the bundled map
+struct Outlines {
+    width: f32,
+    height: f32,
+    shapes: Vec<(String, Vec<Ring>)>, // (ISO code, rings)
+}
+
+// The map file only uses absolute M/L/Z commands, which is all this reads
+fn outlines() -> &'static Outlines {
+    static OUTLINES: OnceLock<Outlines> = OnceLock::new();
+    OUTLINES.get_or_init(|| {
+        let view_box: Vec<f32> = attribute(BUNDLED_MAP, "viewBox")
+            .unwrap_or("")
+            .split_whitespace()
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        let (width, height) = match view_box[..] {
+            [_, _, width, height] => (width, height),
+            _ => (1.0, 1.0),
+        };
+
+        let shapes = BUNDLED_MAP
+            .split("<path")
+            .skip(1)
+            .filter_map(|element| {
+                let id = attribute(element, "id")?;
+                let mut rings = Vec::new();
+                for ring in attribute(element, "d")?.split('Z') {
+                    let numbers: Vec<f32> = ring
+                        .split(|c: char| c.is_ascii_alphabetic() || c.is_whitespace())
+                        .filter_map(|n| n.parse().ok())
+                        .collect();
+                    if numbers.len() >= 6 {
+                        rings.push(numbers.chunks_exact(2).map(|p| (p[0], p[1])).collect());
+                    }
+                }
+                Some((id.to_string(), rings))
+            })
+            .collect();
+        Outlines { width, height, shapes }
+    })
+}
+
+fn attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let start = element.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let len = element[start..].find('"')?;
+    Some(&element[start..start + len])
+}
+
+// Even-odd rule over all rings, so islands and holes both work
+fn contains(rings: &[Ring], x: f32, y: f32) -> bool {
+    let mut inside = false;
+    for ring in rings {
+        for (i, &(x1, y1)) in ring.iter().enumerate() {
+            let (x2, y2) = ring[(i + 1) % ring.len()];
+            if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+fn system_fonts() -> Arc<fontdb::Database> {
+    static FONTS: OnceLock<Arc<fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut database = fontdb::Database::new();
+            database.load_system_fonts();
+            Arc::new(database)
+        })
+        .clone()
+}
+
+/// CSS hex notation for a terminal color. Only RGB colors have an exact
+/// equivalent; the named ANSI colors map to their usual xterm values.
+pub fn hex(color: Color) -> String {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Black => (0, 0, 0),
+        Color::Red => (205, 0, 0),
+        Color::Green => (0, 205, 0),
+        Color::Yellow => (205, 205, 0),
+        Color::Blue => (0, 0, 238),
+        Color::Magenta => (205, 0, 205),
+        Color::Cyan => (0, 205, 205),
+        Color::Gray => (229, 229, 229),
+        Color::DarkGray => (127, 127, 127),
+        Color::LightRed => (255, 0, 0),
+        Color::LightGreen => (0, 255, 0),
+        Color::LightYellow => (255, 255, 0),
+        Color::LightBlue => (92, 92, 255),
+        Color::LightMagenta => (255, 0, 255),
+        Color::LightCyan => (0, 255, 255),
+        _ => (255, 255, 255),
+    };
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
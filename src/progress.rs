@@ -0,0 +1,180 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Version of the progress file layout written by this build. See
+/// `storage::MIGRATIONS` for how older files are upgraded.
+pub const CURRENT_SCHEMA_VERSION: u32 = 3;
+
+/// One entry in the travel journal: a trip to a single prefecture.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Visit {
+    pub prefecture: String, // ISO 3166-2 code, e.g. "JP-13"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_date: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_date: Option<NaiveDate>,
+    pub level: u8, // 0-5, same scale as the prefecture level
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub companions: Vec<String>,
+}
+
+impl Visit {
+    pub fn new(prefecture: &str, level: u8, date: Option<NaiveDate>) -> Self {
+        Self {
+            prefecture: prefecture.to_string(),
+            start_date: date,
+            end_date: None,
+            level,
+            notes: String::new(),
+            companions: Vec::new(),
+        }
+    }
+
+    /// Human readable date or date range, e.g. "2024-03-01 → 2024-03-04".
+    pub fn date_label(&self) -> String {
+        match (self.start_date, self.end_date) {
+            (Some(start), Some(end)) if end != start => format!("{} → {}", start, end),
+            (Some(start), _) => start.to_string(),
+            (None, _) => "undated".to_string(),
+        }
+    }
+
+    /// The last day of the trip, used for "last visited" ordering.
+    pub fn last_date(&self) -> Option<NaiveDate> {
+        self.end_date.or(self.start_date)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserProgress {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub visits: Vec<Visit>,
+    // ISO code -> the user's own labels for the prefecture, e.g. "onsen"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for UserProgress {
+    fn default() -> Self {
+        Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            visits: Vec::new(),
+            tags: BTreeMap::new(),
+        }
+    }
+}
+
+impl UserProgress {
+    /// Effective level of a prefecture: the highest level over all visits.
+    pub fn level(&self, prefecture: &str) -> u8 {
+        self.visits_for(prefecture).map(|v| v.level).max().unwrap_or(0)
+    }
+
+    pub fn visits_for<'a>(&'a self, prefecture: &'a str) -> impl Iterator<Item = &'a Visit> + 'a {
+        self.visits.iter().filter(move |v| v.prefecture == prefecture)
+    }
+
+    pub fn last_visit(&self, prefecture: &str) -> Option<NaiveDate> {
+        self.visits_for(prefecture).filter_map(Visit::last_date).max()
+    }
+
+    /// Record a visit at `level` for today. Repeated presses on the same day
+    /// update that day's entry instead of piling up duplicates. Level 0 means
+    /// "never been there" and clears the prefecture's history.
+    ///
+    /// A prefecture's level is the highest of its visits, so a lower level
+    /// can't be logged while an earlier visit is higher: that level is
+    /// returned as the error and nothing changes.
+    pub fn log_level(&mut self, prefecture: &str, level: u8, today: NaiveDate) -> Result<(), u8> {
+        if level == 0 {
+            self.visits.retain(|v| v.prefecture != prefecture);
+            return Ok(());
+        }
+
+        let earlier = self
+            .visits
+            .iter()
+            .filter(|v| v.prefecture == prefecture && v.start_date != Some(today))
+            .map(|v| v.level)
+            .max()
+            .unwrap_or(0);
+        if level < earlier {
+            return Err(earlier);
+        }
+
+        if let Some(visit) = self
+            .visits
+            .iter_mut()
+            .find(|v| v.prefecture == prefecture && v.start_date == Some(today))
+        {
+            visit.level = level;
+        } else {
+            self.visits.push(Visit::new(prefecture, level, Some(today)));
+        }
+        Ok(())
+    }
+
+    pub fn tags_for(&self, prefecture: &str) -> &[String] {
+        self.tags.get(prefecture).map_or(&[], Vec::as_slice)
+    }
+
+    /// Replace a prefecture's tags. Blank and repeated tags are dropped.
+    pub fn set_tags(&mut self, prefecture: &str, tags: Vec<String>) {
+        let mut cleaned: Vec<String> = Vec::new();
+        for tag in tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !cleaned.iter().any(|t| t == tag) {
+                cleaned.push(tag.to_string());
+            }
+        }
+        if cleaned.is_empty() {
+            self.tags.remove(prefecture);
+        } else {
+            self.tags.insert(prefecture.to_string(), cleaned);
+        }
+    }
+
+    /// Every tag in use, sorted and without repeats.
+    pub fn all_tags(&self) -> Vec<&str> {
+        let mut tags: Vec<&str> = self.tags.values().flatten().map(String::as_str).collect();
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+
+    /// Visits sorted newest first; undated entries go last.
+    pub fn journal(&self) -> Vec<(usize, &Visit)> {
+        let mut entries: Vec<(usize, &Visit)> = self.visits.iter().enumerate().collect();
+        entries.sort_by_key(|(_, v)| std::cmp::Reverse(v.last_date()));
+        entries
+    }
+}
+
+/// Parse "YYYY-MM-DD" or "YYYY-MM-DD..YYYY-MM-DD". An empty string is an
+/// undated visit.
+pub fn parse_date_range(input: &str) -> Result<(Option<NaiveDate>, Option<NaiveDate>), String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok((None, None));
+    }
+
+    let parse = |s: &str| {
+        NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", s.trim()))
+    };
+
+    match input.split_once("..") {
+        Some((start, end)) => {
+            let (start, end) = (parse(start)?, parse(end)?);
+            if end < start {
+                return Err("End date is before start date".to_string());
+            }
+            Ok((Some(start), Some(end)))
+        }
+        None => Ok((Some(parse(input)?), None)),
+    }
+}
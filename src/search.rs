@@ -0,0 +1,88 @@
+use crate::data::{normalize_name, Prefecture, Region};
+use std::cmp::Reverse;
+
+/// Indices of the prefectures matching `query`, best match first. Names in
+/// English, kanji and kana, capitals and region names are all searched;
+/// romaji may be typed with or without macrons or long-vowel spellings
+/// ("Tōkyō", "toukyou") and katakana matches the hiragana readings.
+pub fn search(prefectures: &[Prefecture], regions: &[Region], query: &str) -> Vec<usize> {
+    let query = fold(query);
+    if query.is_empty() {
+        return (0..prefectures.len()).collect();
+    }
+
+    let mut scored: Vec<(u32, usize)> = prefectures
+        .iter()
+        .enumerate()
+        .filter_map(|(index, prefecture)| {
+            let region = regions.iter().find(|r| r.name == prefecture.region);
+            // Region matches rank below the prefecture's own names, so "kan"
+            // lists Kanagawa ahead of the rest of Kanto
+            let fields = [
+                (prefecture.name_en.as_str(), 0),
+                (prefecture.name_jp.as_str(), 0),
+                (prefecture.name_kana.as_str(), 0),
+                (prefecture.capital.as_str(), 100),
+                (prefecture.region.as_str(), 200),
+                (region.map_or("", |r| r.name_jp.as_str()), 200),
+            ];
+            fields
+                .iter()
+                .filter_map(|(field, penalty)| score(&fold(field), &query).map(|s| s.saturating_sub(*penalty)))
+                .max()
+                .map(|best| (best, index))
+        })
+        .collect();
+
+    // Stable, so equal scores keep dataset order
+    scored.sort_by_key(|(score, _)| Reverse(*score));
+    scored.into_iter().map(|(_, index)| index).collect()
+}
+
+/// How well `query` matches `field`, or None if it doesn't. Exact beats
+/// prefix beats substring beats an in-order scattering of the query's
+/// characters, which loses a point for every character skipped.
+pub fn score(field: &str, query: &str) -> Option<u32> {
+    if field.is_empty() {
+        return None;
+    }
+    if field == query {
+        return Some(1000);
+    }
+    if field.starts_with(query) {
+        return Some(800);
+    }
+    if field.contains(query) {
+        return Some(600);
+    }
+
+    let mut wanted = query.chars().peekable();
+    let mut skipped = 0;
+    let mut started = false;
+    for c in field.chars() {
+        match wanted.peek() {
+            Some(&w) if w == c => {
+                wanted.next();
+                started = true;
+            }
+            Some(_) if started => skipped += 1,
+            Some(_) => {}
+            None => break,
+        }
+    }
+    wanted.peek().is_none().then(|| 400u32.saturating_sub(skipped.min(300)))
+}
+
+// Both sides of a comparison go through this: romaji is lowercased with
+// macrons, separators and suffixes dropped (see `normalize_name`), long
+// vowels are collapsed and katakana becomes hiragana
+fn fold(text: &str) -> String {
+    let folded: String = normalize_name(text)
+        .chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect();
+    folded.replace("ou", "o").replace("oo", "o").replace("uu", "u")
+}
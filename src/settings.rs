@@ -0,0 +1,55 @@
+use crate::filter::ListOptions;
+use crate::glyphs::GlyphMode;
+use crate::storage::{get_data_dir, validate_profile_name, write_atomic, DEFAULT_PROFILE};
+use crate::theme::Palette;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+
+/// Preferences kept between sessions in ~/.jterm/settings.json. Missing
+/// fields take their defaults, so files from older versions keep loading.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub list: ListOptions,
+    // Profile used when `--profile` isn't given; see DEFAULT_PROFILE
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    // Built-in or ~/.jterm/themes/<name>.toml; see theme::load_themes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    pub palette: Palette,
+    // Mark levels with glyphs and map patterns as well as color
+    pub patterns: bool,
+    pub glyphs: GlyphMode,
+}
+
+/// The profile to use: the one asked for on the command line, else the
+/// default-profile setting, else DEFAULT_PROFILE.
+pub fn active_profile(requested: Option<String>) -> io::Result<String> {
+    let profile = match requested {
+        Some(profile) => profile,
+        None => load_settings()?.default_profile.unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+    };
+    validate_profile_name(&profile)?;
+    Ok(profile)
+}
+
+pub fn load_settings() -> io::Result<Settings> {
+    let path = get_data_dir()?.join("settings.json");
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    serde_json::from_str(&contents).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+    })
+}
+
+pub fn save_settings(settings: &Settings) -> io::Result<()> {
+    let path = get_data_dir()?.join("settings.json");
+    let contents = serde_json::to_string_pretty(settings)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(&path, contents.as_bytes())
+}
@@ -0,0 +1,481 @@
+use crate::data::{find_prefecture, parse_geo_data, BUNDLED_DATA};
+use crate::progress::{UserProgress, CURRENT_SCHEMA_VERSION};
+use chrono::{Local, NaiveDateTime};
+use serde_json::{json, Map, Value};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Rolling snapshots of progress.json kept in each profile's backups/ directory
+const MAX_BACKUPS: usize = 20;
+// Saves happen on every keypress; only snapshot when the newest backup is
+// older than this so the history spans more than the last few edits.
+const BACKUP_INTERVAL_MINUTES: i64 = 10;
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+// Each entry upgrades a progress document from version `n` to `n + 1`,
+// where `n` is the entry's position plus one. Append new steps at the end
+// and bump CURRENT_SCHEMA_VERSION; never edit a step that has shipped.
+const MIGRATIONS: &[fn(Value) -> io::Result<Value>] = &[migrate_v1_to_v2, migrate_v2_to_v3];
+
+/// Profile used when neither `--profile` nor the default-profile setting
+/// names one. Progress from before profiles existed is moved into it.
+pub const DEFAULT_PROFILE: &str = "default";
+
+pub fn get_data_dir() -> io::Result<PathBuf> {
+    let mut path = dirs::home_dir().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "Could not find home directory")
+    })?;
+    path.push(".jterm");
+
+    if !path.exists() {
+        fs::create_dir_all(&path)?;
+    }
+
+    Ok(path)
+}
+
+/// Directory holding one profile's progress.json and backups,
+/// ~/.jterm/profiles/<name>/. It's created on the first save.
+pub fn get_profile_dir(profile: &str) -> io::Result<PathBuf> {
+    validate_profile_name(profile)?;
+    let data_dir = get_data_dir()?;
+    migrate_legacy_layout(&data_dir)?;
+    Ok(data_dir.join("profiles").join(profile))
+}
+
+/// Names of the profiles that have saved anything, sorted.
+pub fn list_profiles() -> io::Result<Vec<String>> {
+    let data_dir = get_data_dir()?;
+    migrate_legacy_layout(&data_dir)?;
+    let profiles_dir = data_dir.join("profiles");
+    if !profiles_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut profiles: Vec<String> = fs::read_dir(profiles_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| validate_profile_name(name).is_ok())
+        .collect();
+    profiles.sort();
+    Ok(profiles)
+}
+
+/// Profile names become directory names, so keep them to letters, digits,
+/// '-', '_' and '.', not starting with a dot.
+pub fn validate_profile_name(profile: &str) -> io::Result<()> {
+    let valid = !profile.is_empty()
+        && profile.chars().count() <= 64
+        && !profile.starts_with('.')
+        && profile.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid profile name '{}': use letters, digits, '-', '_' or '.'", profile),
+        ))
+    }
+}
+
+// Before profiles, progress.json, its upgrade backups and the backups/
+// directory lived directly in ~/.jterm. Move them into the default profile.
+// progress.json goes last, so an interrupted move is finished next time.
+fn migrate_legacy_layout(data_dir: &Path) -> io::Result<()> {
+    let legacy_file = data_dir.join("progress.json");
+    let profile_dir = data_dir.join("profiles").join(DEFAULT_PROFILE);
+    if !legacy_file.exists() || profile_dir.join("progress.json").exists() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(&profile_dir)?;
+    let mut names: Vec<String> = fs::read_dir(data_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name == "backups" || (name.starts_with("progress.json.v") && name.ends_with(".bak")))
+        .collect();
+    names.push("progress.json".to_string());
+
+    for name in names {
+        fs::rename(data_dir.join(&name), profile_dir.join(&name))?;
+    }
+    Ok(())
+}
+
+pub fn load_user_progress(profile: &str) -> io::Result<UserProgress> {
+    load_progress_file(&get_profile_dir(profile)?.join("progress.json"))
+}
+
+/// A profile's progress read without changing anything on disk; see
+/// `read_progress_file`.
+pub fn read_user_progress(profile: &str) -> io::Result<UserProgress> {
+    read_progress_file(&get_profile_dir(profile)?.join("progress.json"))
+}
+
+pub fn save_user_progress(profile: &str, progress: &UserProgress) -> io::Result<()> {
+    let profile_dir = get_profile_dir(profile)?;
+    fs::create_dir_all(&profile_dir)?;
+    let progress_file = profile_dir.join("progress.json");
+
+    snapshot(&progress_file, &profile_dir.join("backups"), false)?;
+    save_progress_file(&progress_file, progress)
+}
+
+/// Load a progress file, upgrading it in place if it was written by an older
+/// version. The original is kept next to it as `<name>.v<version>.bak`.
+pub fn load_progress_file(path: &Path) -> io::Result<UserProgress> {
+    if !path.exists() {
+        return Ok(UserProgress::default());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    let document: Value = serde_json::from_str(&contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let version = schema_version(&document)?;
+    let progress = parse_progress(document)?;
+
+    if version < CURRENT_SCHEMA_VERSION {
+        let backup = backup_path(path, version);
+        write_atomic(&backup, contents.as_bytes())?;
+        save_progress_file(path, &progress)?;
+    }
+
+    Ok(progress)
+}
+
+/// Read a progress file for display only. An older version is upgraded in
+/// memory; the file is never rewritten and no backup is made.
+pub fn read_progress_file(path: &Path) -> io::Result<UserProgress> {
+    if !path.exists() {
+        return Ok(UserProgress::default());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    let document: Value = serde_json::from_str(&contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    parse_progress(document)
+}
+
+pub fn save_progress_file(path: &Path, progress: &UserProgress) -> io::Result<()> {
+    let contents = serde_json::to_string_pretty(progress)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    write_atomic(path, contents.as_bytes())
+}
+
+/// Write to a temporary file in the same directory, fsync it and rename it
+/// over `path`, so a crash leaves either the old or the new file intact.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("progress.json");
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
+    }
+
+    // Persist the rename itself. Directories can't be opened for syncing on
+    // every platform, so this is best effort.
+    if let Some(parent) = path.parent()
+        && let Ok(dir) = File::open(parent)
+    {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+pub struct Backup {
+    pub path: PathBuf,
+    pub created: NaiveDateTime,
+}
+
+/// Snapshots in a profile's backups directory, newest first.
+pub fn list_backups(profile: &str) -> io::Result<Vec<Backup>> {
+    list_backups_in(&get_profile_dir(profile)?.join("backups"))
+}
+
+fn list_backups_in(backups_dir: &Path) -> io::Result<Vec<Backup>> {
+    if !backups_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups: Vec<Backup> = fs::read_dir(backups_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let stamp = name.strip_prefix("progress-")?.strip_suffix(".json")?;
+            let created = NaiveDateTime::parse_from_str(stamp, BACKUP_TIME_FORMAT).ok()?;
+            Some(Backup { path: entry.path(), created })
+        })
+        .collect();
+
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created));
+    Ok(backups)
+}
+
+/// Copy the current progress file into the backups directory. Unless
+/// `force` is set this is skipped when a recent snapshot already exists.
+fn snapshot(progress_file: &Path, backups_dir: &Path, force: bool) -> io::Result<()> {
+    if !progress_file.exists() {
+        return Ok(());
+    }
+
+    let now = Local::now().naive_local();
+    let backups = list_backups_in(backups_dir)?;
+    if !force
+        && let Some(latest) = backups.first()
+        && now.signed_duration_since(latest.created).num_minutes() < BACKUP_INTERVAL_MINUTES
+    {
+        return Ok(());
+    }
+
+    fs::create_dir_all(backups_dir)?;
+    let target = backups_dir.join(format!("progress-{}.json", now.format(BACKUP_TIME_FORMAT)));
+    if !target.exists() {
+        write_atomic(&target, &fs::read(progress_file)?)?;
+    }
+
+    for old in list_backups_in(backups_dir)?.iter().skip(MAX_BACKUPS) {
+        fs::remove_file(&old.path)?;
+    }
+
+    Ok(())
+}
+
+/// Replace a profile's progress.json with a snapshot. The current file is
+/// snapshotted first so a restore can itself be undone.
+pub fn restore_backup(profile: &str, backup: &Backup) -> io::Result<UserProgress> {
+    let profile_dir = get_profile_dir(profile)?;
+    let progress_file = profile_dir.join("progress.json");
+
+    let contents = fs::read_to_string(&backup.path)?;
+    let document: Value = serde_json::from_str(&contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let progress = parse_progress(document)?;
+
+    snapshot(&progress_file, &profile_dir.join("backups"), true)?;
+    save_progress_file(&progress_file, &progress)?;
+    Ok(progress)
+}
+
+/// Run every migration the document needs and deserialize the result.
+pub fn parse_progress(mut document: Value) -> io::Result<UserProgress> {
+    let version = schema_version(&document)?;
+
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "progress file has schema version {} but this jterm only understands up to {}; please upgrade jterm",
+                version, CURRENT_SCHEMA_VERSION
+            ),
+        ));
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        document = migration(document)?;
+    }
+
+    serde_json::from_value(document).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Files written before versioning was introduced have no `schema_version`
+/// and are treated as version 1.
+fn schema_version(document: &Value) -> io::Result<u32> {
+    match document.get("schema_version") {
+        None => Ok(1),
+        Some(value) => value
+            .as_u64()
+            .filter(|v| *v >= 1)
+            .map(|v| v as u32)
+            .ok_or_else(|| invalid("schema_version must be a positive integer")),
+    }
+}
+
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("progress.json");
+    path.with_file_name(format!("{}.v{}.bak", file_name, version))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// v1 stored a single level per prefecture in `prefecture_levels`. v2 keeps a
+/// journal of visits; each old level becomes one undated visit.
+fn migrate_v1_to_v2(document: Value) -> io::Result<Value> {
+    let levels = match document.get("prefecture_levels") {
+        Some(Value::Object(levels)) => levels.clone(),
+        Some(_) => return Err(invalid("prefecture_levels must be an object")),
+        None => Map::new(),
+    };
+
+    let mut levels: Vec<(String, u64)> = levels
+        .into_iter()
+        .map(|(name, level)| {
+            level
+                .as_u64()
+                .filter(|level| *level <= 5)
+                .map(|level| (name, level))
+                .ok_or_else(|| invalid("prefecture levels must be numbers from 0 to 5"))
+        })
+        .collect::<io::Result<_>>()?;
+    levels.sort();
+
+    // Keep any journal entries already present alongside the old levels
+    let mut visits = match document.get("visits") {
+        Some(Value::Array(visits)) => visits.clone(),
+        Some(_) => return Err(invalid("visits must be an array")),
+        None => Vec::new(),
+    };
+    visits.extend(
+        levels
+            .into_iter()
+            .filter(|(_, level)| *level > 0)
+            .map(|(name, level)| json!({ "prefecture": name, "level": level })),
+    );
+
+    Ok(json!({ "schema_version": 2, "visits": visits }))
+}
+
+/// v2 keyed visits by English name. v3 uses the ISO 3166-2 code so renamed
+/// or differently romanized names ("Hyōgo") no longer orphan data. Names
+/// that match no prefecture are left as they are rather than dropped.
+fn migrate_v2_to_v3(mut document: Value) -> io::Result<Value> {
+    // Always map against the bundled dataset so the result doesn't depend on
+    // a user's data override
+    let prefectures = parse_geo_data(BUNDLED_DATA)?.prefectures;
+
+    if let Some(Value::Array(visits)) = document.get_mut("visits") {
+        for visit in visits {
+            let Some(name) = visit.get("prefecture").and_then(Value::as_str) else {
+                return Err(invalid("every visit needs a prefecture"));
+            };
+            if let Some(prefecture) = find_prefecture(&prefectures, name) {
+                visit["prefecture"] = json!(prefecture.iso_code);
+            }
+        }
+    }
+
+    document["schema_version"] = json!(3);
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Visit;
+    use chrono::NaiveDate;
+
+    // An empty directory of its own under the system temp dir
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jterm-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn progress(visits: Vec<Visit>) -> UserProgress {
+        UserProgress { visits, ..UserProgress::default() }
+    }
+
+    // Load `fixture` from disk as a version `version` file, check it comes
+    // out as `expected` with the original kept as a backup if it needed
+    // upgrading, then check that saving and loading again changes nothing
+    fn check_round_trip(fixture: Value, version: u32, expected: UserProgress) {
+        assert_eq!(parse_progress(fixture.clone()).unwrap(), expected);
+
+        let dir = temp_dir(&format!("v{}", version));
+        let path = dir.join("progress.json");
+        let original = serde_json::to_string_pretty(&fixture).unwrap();
+        fs::write(&path, &original).unwrap();
+
+        let loaded = load_progress_file(&path).unwrap();
+        assert_eq!(loaded, expected);
+        let backup = dir.join(format!("progress.json.v{}.bak", version));
+        if version < CURRENT_SCHEMA_VERSION {
+            assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+        } else {
+            assert!(!backup.exists(), "a current file shouldn't be backed up");
+        }
+
+        save_progress_file(&path, &loaded).unwrap();
+        assert_eq!(load_progress_file(&path).unwrap(), loaded);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn v1_levels_become_undated_visits() {
+        let fixture = json!({ "prefecture_levels": { "Tokyo": 4, "Hokkaido": 2, "Osaka": 0 } });
+        let expected = progress(vec![Visit::new("JP-01", 2, None), Visit::new("JP-13", 4, None)]);
+        check_round_trip(fixture, 1, expected);
+    }
+
+    #[test]
+    fn v2_names_become_iso_codes() {
+        let fixture = json!({
+            "schema_version": 2,
+            "visits": [
+                { "prefecture": "Kyoto", "level": 3, "start_date": "2023-04-01", "notes": "Temples" },
+                { "prefecture": "Hyōgo", "level": 2 },
+                { "prefecture": "Atlantis", "level": 1 },
+            ],
+        });
+        let mut kyoto = Visit::new("JP-26", 3, NaiveDate::from_ymd_opt(2023, 4, 1));
+        kyoto.notes = "Temples".to_string();
+        // Unknown names are kept rather than dropped
+        let expected = progress(vec![kyoto, Visit::new("JP-28", 2, None), Visit::new("Atlantis", 1, None)]);
+        check_round_trip(fixture, 2, expected);
+    }
+
+    #[test]
+    fn v3_loads_unchanged() {
+        let fixture = json!({
+            "schema_version": 3,
+            "visits": [
+                { "prefecture": "JP-47", "level": 5, "start_date": "2020-01-01", "end_date": "2021-12-31",
+                  "companions": ["Aiko"] },
+            ],
+            "tags": { "JP-47": ["beach"] },
+        });
+        let mut okinawa = Visit::new("JP-47", 5, NaiveDate::from_ymd_opt(2020, 1, 1));
+        okinawa.end_date = NaiveDate::from_ymd_opt(2021, 12, 31);
+        okinawa.companions = vec!["Aiko".to_string()];
+        let mut expected = progress(vec![okinawa]);
+        expected.tags.insert("JP-47".to_string(), vec!["beach".to_string()]);
+        check_round_trip(fixture, 3, expected);
+    }
+
+    #[test]
+    fn reading_an_old_file_leaves_it_alone() {
+        let dir = temp_dir("read-only");
+        let path = dir.join("progress.json");
+        let original = r#"{ "prefecture_levels": { "Tokyo": 4 } }"#;
+        fs::write(&path, original).unwrap();
+
+        let progress = read_progress_file(&path).unwrap();
+        assert_eq!(progress.level("JP-13"), 4);
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "no backup should be written");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let fixture = json!({ "schema_version": CURRENT_SCHEMA_VERSION + 1, "visits": [] });
+        assert!(parse_progress(fixture).is_err());
+    }
+}
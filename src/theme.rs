@@ -0,0 +1,318 @@
+use crate::map::Pattern;
+use crate::storage::get_data_dir;
+use ratatui::style::{Color, Style};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+pub const DEFAULT_THEME: &str = "flexoki-light";
+
+/// The colors every widget draws with. Levels 1-5 are the colors a
+/// prefecture is shown in at that level; level 0 is the unvisited text
+/// color, while unvisited prefectures on the map are left in `background`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub background: Color,
+    pub text: Color,
+    pub muted: Color,     // hints and secondary labels
+    pub accent: Color,    // focused prompt rows and input
+    pub selection: Color, // background of the selected row
+    pub info: Color,
+    pub success: Color,
+    pub warning: Color,
+    pub error: Color,
+    pub levels: [Color; 6],
+}
+
+impl Theme {
+    /// Text on the theme's background, for whole screens and popups.
+    pub fn base(&self) -> Style {
+        Style::default().fg(self.text).bg(self.background)
+    }
+
+    pub fn level(&self, level: u8) -> Color {
+        self.levels.get(level as usize).copied().unwrap_or(self.text)
+    }
+
+    /// Fill for a prefecture on the choropleth. Unvisited ones use the
+    /// paper color; the text color used for level 0 elsewhere would read
+    /// as visited.
+    pub fn map_fill(&self, level: u8) -> Color {
+        match level {
+            0 => self.background,
+            _ => self.level(level),
+        }
+    }
+
+    /// This theme with `palette`'s level colors, if it has any.
+    pub fn with_palette(mut self, palette: Palette) -> Theme {
+        if let Some(levels) = palette.levels() {
+            self.levels[1..].copy_from_slice(&levels);
+        }
+        self
+    }
+
+    /// Color for a progress bar at `percentage` complete.
+    pub fn progress(&self, percentage: u32) -> Color {
+        match percentage {
+            0..20 => self.error,
+            20..50 => self.warning,
+            50..75 => self.accent,
+            _ => self.success,
+        }
+    }
+}
+
+/// Level colors that replace a theme's for readers with color vision
+/// deficiency. Each set was picked from the Okabe-Ito and Tol palettes to
+/// keep all five levels apart under a simulation of that deficiency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    #[default]
+    Theme, // the theme's own level colors
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 4] = [Palette::Theme, Palette::Deuteranopia, Palette::Protanopia, Palette::Tritanopia];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Palette::Theme => "theme colors",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::Protanopia => "protanopia",
+            Palette::Tritanopia => "tritanopia",
+        }
+    }
+
+    // Colors for levels 1-5
+    fn levels(self) -> Option<[Color; 5]> {
+        let rgb = |hex: u32| Color::from_u32(hex);
+        match self {
+            Palette::Theme => None,
+            Palette::Deuteranopia => Some([rgb(0xffc107), rgb(0x999933), rgb(0x88ccee), rgb(0x785ef0), rgb(0x882255)]),
+            Palette::Protanopia => Some([rgb(0xffc107), rgb(0x117733), rgb(0x88ccee), rgb(0x785ef0), rgb(0x882255)]),
+            Palette::Tritanopia => Some([rgb(0xfe6100), rgb(0xffc107), rgb(0x88ccee), rgb(0x777777), rgb(0x882255)]),
+        }
+    }
+}
+
+/// Marks that tell levels 0-5 apart without color: a circle filling up in
+/// the TUI, and hatching on the exported map.
+pub const LEVEL_GLYPHS: [&str; 6] = ["·", "○", "◔", "◑", "◕", "●"];
+
+/// LEVEL_GLYPHS for ASCII mode.
+pub const ASCII_LEVEL_GLYPHS: [&str; 6] = [".", "1", "2", "3", "4", "5"];
+
+pub fn level_pattern(level: u8) -> Option<Pattern> {
+    match level {
+        1 => Some(Pattern::Dots),
+        2 => Some(Pattern::Lines),
+        3 => Some(Pattern::Diagonal),
+        4 => Some(Pattern::Crosshatch),
+        5 => Some(Pattern::Grid),
+        _ => None,
+    }
+}
+
+/// The themes that ship with jterm, the default first.
+pub fn builtin_themes() -> Vec<Theme> {
+    vec![
+        // Flexoki (https://stephango.com/flexoki), on paper
+        Theme {
+            name: DEFAULT_THEME.to_string(),
+            background: Color::Rgb(252, 249, 243), // #fcf9f3
+            text: Color::Rgb(16, 15, 13),          // #100f0d
+            muted: Color::Rgb(87, 82, 74),         // #57524a
+            accent: Color::Rgb(72, 108, 166),      // #486ca6
+            selection: Color::Rgb(215, 204, 183),  // #d7ccb7
+            info: Color::Rgb(36, 139, 142),        // #248b8e
+            success: Color::Rgb(66, 130, 62),      // #42823e
+            warning: Color::Rgb(173, 135, 29),     // #ad871d
+            error: Color::Rgb(175, 75, 74),        // #af4b4a
+            levels: [
+                Color::Rgb(16, 15, 13),
+                Color::Rgb(175, 75, 74),
+                Color::Rgb(173, 135, 29),
+                Color::Rgb(66, 130, 62),
+                Color::Rgb(137, 89, 168), // #8959a8
+                Color::Rgb(72, 108, 166),
+            ],
+        },
+        // Flexoki's dark side: the 400 accents on black
+        Theme {
+            name: "flexoki-dark".to_string(),
+            background: Color::Rgb(16, 15, 15), // #100f0f
+            text: Color::Rgb(206, 205, 195),    // #cecdc3
+            muted: Color::Rgb(135, 133, 128),   // #878580
+            accent: Color::Rgb(67, 133, 190),   // #4385be
+            selection: Color::Rgb(52, 51, 49),  // #343331
+            info: Color::Rgb(58, 169, 159),     // #3aa99f
+            success: Color::Rgb(135, 154, 57),  // #879a39
+            warning: Color::Rgb(208, 162, 21),  // #d0a215
+            error: Color::Rgb(209, 77, 65),     // #d14d41
+            levels: [
+                Color::Rgb(206, 205, 195),
+                Color::Rgb(209, 77, 65),
+                Color::Rgb(208, 162, 21),
+                Color::Rgb(135, 154, 57),
+                Color::Rgb(139, 126, 200), // #8b7ec8
+                Color::Rgb(67, 133, 190),
+            ],
+        },
+        solarized("solarized-light", true),
+        solarized("solarized-dark", false),
+        // Pure black and white with the terminal's bright ANSI colors
+        Theme {
+            name: "high-contrast".to_string(),
+            background: Color::Black,
+            text: Color::White,
+            muted: Color::Gray,
+            accent: Color::LightCyan,
+            selection: Color::Blue,
+            info: Color::LightCyan,
+            success: Color::LightGreen,
+            warning: Color::LightYellow,
+            error: Color::LightRed,
+            levels: [
+                Color::White,
+                Color::LightRed,
+                Color::LightYellow,
+                Color::LightGreen,
+                Color::LightMagenta,
+                Color::LightCyan,
+            ],
+        },
+    ]
+}
+
+// Solarized (https://ethanschoonover.com/solarized) shares its accents
+// between the two variants and swaps the base tones
+fn solarized(name: &str, light: bool) -> Theme {
+    let base03 = Color::Rgb(0, 43, 54); // #002b36
+    let base02 = Color::Rgb(7, 54, 66); // #073642
+    let base01 = Color::Rgb(88, 110, 117); // #586e75
+    let base00 = Color::Rgb(101, 123, 131); // #657b83
+    let base0 = Color::Rgb(131, 148, 150); // #839496
+    let base1 = Color::Rgb(147, 161, 161); // #93a1a1
+    let base2 = Color::Rgb(238, 232, 213); // #eee8d5
+    let base3 = Color::Rgb(253, 246, 227); // #fdf6e3
+    let (yellow, red, violet) = (Color::Rgb(181, 137, 0), Color::Rgb(220, 50, 47), Color::Rgb(108, 113, 196));
+    let (blue, cyan, green) = (Color::Rgb(38, 139, 210), Color::Rgb(42, 161, 152), Color::Rgb(133, 153, 0));
+
+    let (background, text, muted, selection) =
+        if light { (base3, base00, base1, base2) } else { (base03, base0, base01, base02) };
+    Theme {
+        name: name.to_string(),
+        background,
+        text,
+        muted,
+        accent: blue,
+        selection,
+        info: cyan,
+        success: green,
+        warning: yellow,
+        error: red,
+        levels: [text, red, yellow, green, violet, blue],
+    }
+}
+
+// A theme file in ~/.jterm/themes. Colors are names ("red") or "#rrggbb";
+// anything left out comes from `base`, or the default theme.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    base: Option<String>,
+    background: Option<String>,
+    text: Option<String>,
+    muted: Option<String>,
+    accent: Option<String>,
+    selection: Option<String>,
+    info: Option<String>,
+    success: Option<String>,
+    warning: Option<String>,
+    error: Option<String>,
+    levels: Option<Vec<String>>,
+}
+
+/// Built-in themes followed by the user's, and a message for every theme
+/// file that couldn't be read. A user theme named like a built-in one
+/// replaces it.
+pub fn load_themes() -> (Vec<Theme>, Vec<String>) {
+    let mut themes = builtin_themes();
+    let mut errors = Vec::new();
+
+    let dir = match get_data_dir() {
+        Ok(data_dir) => data_dir.join("themes"),
+        Err(err) => return (themes, vec![err.to_string()]),
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return (themes, errors);
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let theme = fs::read_to_string(&path).and_then(|contents| parse_theme(name, &contents, &themes));
+        match theme {
+            Ok(theme) => match themes.iter_mut().find(|t| t.name == theme.name) {
+                Some(existing) => *existing = theme,
+                None => themes.push(theme),
+            },
+            Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+        }
+    }
+    (themes, errors)
+}
+
+fn parse_theme(name: &str, contents: &str, known: &[Theme]) -> io::Result<Theme> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let file: ThemeFile = toml::from_str(contents).map_err(|e| invalid(e.message().to_string()))?;
+
+    let base_name = file.base.as_deref().unwrap_or(DEFAULT_THEME);
+    let mut theme = known
+        .iter()
+        .find(|t| t.name == base_name)
+        .cloned()
+        .ok_or_else(|| invalid(format!("unknown base theme '{}'", base_name)))?;
+    theme.name = name.to_string();
+
+    let color = |value: &str| Color::from_str(value).map_err(|_| invalid(format!("'{}' is not a color", value)));
+    let fields = [
+        (&file.background, &mut theme.background),
+        (&file.text, &mut theme.text),
+        (&file.muted, &mut theme.muted),
+        (&file.accent, &mut theme.accent),
+        (&file.selection, &mut theme.selection),
+        (&file.info, &mut theme.info),
+        (&file.success, &mut theme.success),
+        (&file.warning, &mut theme.warning),
+        (&file.error, &mut theme.error),
+    ];
+    for (value, field) in fields {
+        if let Some(value) = value {
+            *field = color(value)?;
+        }
+    }
+    if let Some(levels) = &file.levels {
+        if levels.len() != 6 {
+            return Err(invalid(format!("levels needs 6 colors (0-5), got {}", levels.len())));
+        }
+        for (slot, value) in theme.levels.iter_mut().zip(levels) {
+            *slot = color(value)?;
+        }
+    }
+    Ok(theme)
+}